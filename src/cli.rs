@@ -0,0 +1,638 @@
+use crate::config::{Config, ConfigStore, Overrides};
+use crate::journal::Journal;
+use crate::known_folders;
+use crate::metadata;
+use crate::plan::{self, DeletionPlan};
+use crate::protected;
+use crate::registry::{self, RegistryBackend};
+use crate::scan::{self, scan_for_leftovers, Leftover, ScanControl, ScanOptions};
+use crate::uninstall::{self, UninstallOutcome};
+use crate::trace::{InstallManifest, ManifestStore};
+use crate::{create_plan, delete_items, execute_plan, finish_trace, is_elevated, load_installed_programs, load_rules, start_trace, trace_roots, DeleteError, FoundItem, ProgramInfo};
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// --- Exit Codes ---
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_NOTHING_FOUND: i32 = 2;
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+pub const EXIT_NOTHING_DELETED: i32 = 4;
+
+const USAGE: &str = "\
+Usage:
+  kuri_uninstaller list [--json]
+  kuri_uninstaller scan <program> [--signed-out-users] [--profiles-root <dir>] [--max-depth <n>] [--aggressive] [--protect <path>] [--json]
+  kuri_uninstaller uninstall <program> [--quiet] [--signed-out-users] [--json]
+  kuri_uninstaller clean <program> [--yes] [--backup] [--all] [--dry-run] [--signed-out-users] [--aggressive] [--protect <path>] [--json]
+  kuri_uninstaller apply <plan-file> [--yes] [--backup] [--protect <path>] [--json]
+  kuri_uninstaller trace start|finish|cancel [--json]
+
+Options:
+  --json     Print results as JSON instead of a table (`clean` and `apply` also need --yes)
+  --yes      Delete without asking for confirmation
+  --backup   Export registry keys to a .reg file before deleting them
+  --all      Also delete low-confidence matches (by default only high-confidence ones)
+  --dry-run  Save a deletion plan instead of deleting; run it later with `apply`
+  --quiet    Run the program's unattended uninstaller (QuietUninstallString, MsiExec /qn)
+  --signed-out-users, --no-signed-out-users
+             Also scan the registry of users who are not signed in (mounts their NTUSER.DAT), or don't
+  --profiles-root <dir>
+             Also treat every folder in <dir> as a user profile and scan its AppData
+  --max-depth <n>
+             Look at most <n> folders deep below each searched folder (default 8, or the saved setting)
+  --aggressive, --no-aggressive
+             Also match the program's name inside other words (git in digital), or don't; by
+             default names only match on word boundaries
+  --protect <path>
+             Never find or delete this folder or registry key, or anything inside it (repeatable)
+
+Settings saved in %APPDATA%\\KuriUninstaller\\config.toml (exclusions, extra search locations,
+protected locations, search depth, aggressive matching) apply as well. The options above win over
+saved settings, and add to saved lists.
+
+Exit codes:
+  0  success
+  1  error
+  2  nothing found
+  3  some items could not be deleted
+  4  nothing was deleted (protected items, failed backup, or a plan that no longer matches)";
+
+// --- Argument Parsing ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliCommand {
+    List,
+    Scan { program: String },
+    Uninstall { program: String, quiet: bool },
+    Clean { program: String, yes: bool, backup: bool, all: bool, dry_run: bool },
+    Apply { plan: PathBuf, yes: bool, backup: bool },
+    TraceStart,
+    TraceFinish,
+    TraceCancel,
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliArgs {
+    pub command: CliCommand,
+    pub json: bool,
+    pub scan_options: ScanOptions,
+    pub overrides: Overrides,
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    let mut positional = Vec::new();
+    let mut scan_options = ScanOptions::default();
+    let mut overrides = Overrides::default();
+    let (mut json, mut yes, mut backup, mut all, mut dry_run, mut quiet) = (false, false, false, false, false, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--yes" | "-y" => yes = true,
+            "--backup" => backup = true,
+            "--all" => all = true,
+            "--dry-run" => dry_run = true,
+            "--quiet" | "-q" => quiet = true,
+            "--signed-out-users" => overrides.include_signed_out_users = Some(true),
+            "--no-signed-out-users" => overrides.include_signed_out_users = Some(false),
+            "--aggressive" => overrides.aggressive = Some(true),
+            "--no-aggressive" => overrides.aggressive = Some(false),
+            "--profiles-root" => {
+                scan_options.profiles_root = Some(args.next().ok_or("`--profiles-root` needs a folder")?.into());
+            }
+            "--max-depth" => {
+                let depth = args.next().ok_or("`--max-depth` needs a number")?;
+                overrides.max_depth = Some(depth.parse().map_err(|_| format!("Invalid depth: {}", depth))?);
+            }
+            "--protect" => {
+                scan_options.protected.push(args.next().ok_or("`--protect` needs a folder or registry key")?.clone());
+            }
+            "--help" | "-h" => return Ok(CliArgs { command: CliCommand::Help, json, scan_options, overrides }),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let command = match positional.next().as_deref() {
+        Some("list") => CliCommand::List,
+        Some("scan") => CliCommand::Scan {
+            program: positional.next().ok_or("`scan` needs a program name")?,
+        },
+        Some("uninstall") => CliCommand::Uninstall {
+            program: positional.next().ok_or("`uninstall` needs a program name")?,
+            quiet,
+        },
+        Some("clean") => CliCommand::Clean {
+            program: positional.next().ok_or("`clean` needs a program name")?,
+            yes,
+            backup,
+            all,
+            dry_run,
+        },
+        Some("apply") => CliCommand::Apply {
+            plan: positional.next().ok_or("`apply` needs a plan file")?.into(),
+            yes,
+            backup,
+        },
+        Some("trace") => match positional.next().as_deref() {
+            Some("start") => CliCommand::TraceStart,
+            Some("finish") => CliCommand::TraceFinish,
+            Some("cancel") => CliCommand::TraceCancel,
+            _ => return Err("`trace` needs `start`, `finish` or `cancel`".to_string()),
+        },
+        Some("help") => CliCommand::Help,
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+        None => return Err("No command given".to_string()),
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument: {} (quote program names that contain spaces)", extra));
+    }
+    // The table and confirmation prompt would end up in the JSON.
+    if json && matches!(command, CliCommand::Clean { yes: false, dry_run: false, .. } | CliCommand::Apply { yes: false, .. }) {
+        return Err("`--json` needs `--yes` when deleting".to_string());
+    }
+
+    Ok(CliArgs { command, json, scan_options, overrides })
+}
+
+// --- Commands ---
+
+pub fn run(args: &[String]) -> i32 {
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return EXIT_ERROR;
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start runtime: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let registry = registry::default_backend();
+    let config = match ConfigStore::default_location().map_or(Ok(Config::default()), |store| store.load()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load settings: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let options = config.merge_into(args.scan_options, &args.overrides);
+
+    let result = runtime.block_on(async {
+        match args.command {
+            CliCommand::Help => {
+                println!("{}", USAGE);
+                Ok(EXIT_SUCCESS)
+            }
+            CliCommand::List => list(registry, args.json).await,
+            CliCommand::Scan { program } => scan(registry, &program, options, args.json).await,
+            CliCommand::Uninstall { program, quiet } => uninstall(registry, &program, quiet, options, args.json).await,
+            CliCommand::Clean { program, all, dry_run: true, .. } => {
+                dry_run(registry, &program, all, options, args.json).await
+            }
+            CliCommand::Clean { program, yes, backup, all, .. } => {
+                clean(registry, &program, yes, backup, all, options, args.json).await
+            }
+            CliCommand::Apply { plan, yes, backup } => apply(registry, &plan, yes, backup, options, args.json).await,
+            CliCommand::TraceStart => {
+                start_trace(trace_roots(known_folders::default_resolver().as_ref()), manifest_store()?, registry).await?;
+                eprintln!("Snapshot taken. Run the installer, then `kuri_uninstaller trace finish`.");
+                Ok(EXIT_SUCCESS)
+            }
+            CliCommand::TraceFinish => trace_finish(registry, args.json).await,
+            CliCommand::TraceCancel => {
+                manifest_store()?.discard_snapshot()?;
+                Ok(EXIT_SUCCESS)
+            }
+        }
+    });
+
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        EXIT_ERROR
+    })
+}
+
+async fn list(registry: Arc<dyn RegistryBackend>, json: bool) -> Result<i32, String> {
+    let programs = load_installed_programs(registry).await?;
+    if json {
+        print_json(&programs)?;
+    } else {
+        let rows: Vec<[String; 3]> = programs
+            .iter()
+            .map(|p| {
+                let location = p.install_location.as_ref().map(|l| l.display().to_string()).unwrap_or_default();
+                [p.name.clone(), p.version.clone(), location]
+            })
+            .collect();
+        print_table(["NAME", "VERSION", "INSTALL LOCATION"], &rows);
+    }
+    Ok(if programs.is_empty() { EXIT_NOTHING_FOUND } else { EXIT_SUCCESS })
+}
+
+async fn scan(registry: Arc<dyn RegistryBackend>, query: &str, options: ScanOptions, json: bool) -> Result<i32, String> {
+    let program = find_program(registry.clone(), query).await?;
+    let items = find_leftovers(&program, registry, options).await?;
+
+    if json {
+        print_json(&ScanReport { program: &program, items: &items })?;
+    } else {
+        print_leftovers(&items);
+    }
+    Ok(if items.is_empty() { EXIT_NOTHING_FOUND } else { EXIT_SUCCESS })
+}
+
+// Runs the vendor uninstaller, then scans for what it left behind.
+async fn uninstall(
+    registry: Arc<dyn RegistryBackend>,
+    query: &str,
+    quiet: bool,
+    options: ScanOptions,
+    json: bool,
+) -> Result<i32, String> {
+    if !is_elevated() {
+        return Err("`uninstall` must be run from an Administrator terminal".to_string());
+    }
+
+    let program = find_program(registry.clone(), query).await?;
+    let command = uninstall::uninstall_command(&program, quiet)?;
+    if !json {
+        println!("Running {}", command);
+    }
+    let outcome = uninstall::run_uninstaller(command).await?;
+    if !outcome.succeeded() {
+        if json {
+            print_json(&UninstallReport { program: &program, uninstall: &outcome, items: &[] })?;
+        }
+        return Err(outcome.describe());
+    }
+
+    let items = find_leftovers(&program, registry, options).await?;
+    if json {
+        print_json(&UninstallReport { program: &program, uninstall: &outcome, items: &items })?;
+    } else {
+        println!("{}.", outcome.describe());
+        print_leftovers(&items);
+    }
+    Ok(if items.is_empty() { EXIT_NOTHING_FOUND } else { EXIT_SUCCESS })
+}
+
+async fn clean(
+    registry: Arc<dyn RegistryBackend>,
+    query: &str,
+    yes: bool,
+    backup: bool,
+    all: bool,
+    options: ScanOptions,
+    json: bool,
+) -> Result<i32, String> {
+    if !is_elevated() {
+        return Err("`clean` must be run from an Administrator terminal".to_string());
+    }
+
+    let program = find_program(registry.clone(), query).await?;
+    let protected = protected::system_protection(registry.as_ref(), known_folders::default_resolver().as_ref(), &options);
+    let items = select(find_leftovers(&program, registry.clone(), options).await?, all);
+    if items.is_empty() {
+        if json {
+            print_json(&CleanReport { program: &program, items: &[], errors: vec![] })?;
+        } else {
+            println!("No leftovers found for {}.", program.name);
+        }
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+
+    if !yes {
+        print_leftovers(&items);
+        if !confirm(&format!("Delete these {} items?", items.len()))? {
+            eprintln!("Aborted.");
+            return Ok(EXIT_ERROR);
+        }
+    }
+
+    let journal = Journal::default_location().ok_or("Could not find the application data directory")?;
+    let targets = items.iter().map(|l| l.item.clone()).collect();
+    let result = delete_items(program.name.clone(), targets, backup, registry, journal, protected).await;
+    let (failed, errors, code) = deletion_outcome(result, items.len());
+
+    if json {
+        print_json(&CleanReport { program: &program, items: &items, errors })?;
+    } else {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        println!("Processed {} items for {}, {} could not be deleted.", items.len(), program.name, failed);
+    }
+    Ok(code)
+}
+
+async fn dry_run(
+    registry: Arc<dyn RegistryBackend>,
+    query: &str,
+    all: bool,
+    options: ScanOptions,
+    json: bool,
+) -> Result<i32, String> {
+    let program = find_program(registry.clone(), query).await?;
+    let leftovers = select(find_leftovers(&program, registry.clone(), options).await?, all);
+    let items = leftovers.into_iter().map(|l| l.item).collect();
+    let (plan, path) = create_plan(program.name.clone(), items, registry).await?;
+
+    if json {
+        print_json(&plan)?;
+    } else {
+        print!("{}", plan.to_text());
+    }
+    eprintln!("Plan saved to {}", path.display());
+    Ok(if plan.operations.is_empty() { EXIT_NOTHING_FOUND } else { EXIT_SUCCESS })
+}
+
+async fn apply(
+    registry: Arc<dyn RegistryBackend>,
+    plan_path: &Path,
+    yes: bool,
+    backup: bool,
+    options: ScanOptions,
+    json: bool,
+) -> Result<i32, String> {
+    if !is_elevated() {
+        return Err("`apply` must be run from an Administrator terminal".to_string());
+    }
+
+    let plan = DeletionPlan::load(plan_path)?;
+    let items = plan.items();
+    if items.is_empty() {
+        if json {
+            print_json(&ApplyReport { program: &plan.program, items: &[], errors: vec![] })?;
+        } else {
+            println!("The plan contains nothing to delete.");
+        }
+        return Ok(EXIT_NOTHING_FOUND);
+    }
+
+    if !yes {
+        print!("{}", plan.to_text());
+        if !confirm(&format!("Execute these {} operations?", items.len()))? {
+            eprintln!("Aborted.");
+            return Ok(EXIT_ERROR);
+        }
+    }
+
+    let journal = Journal::default_location().ok_or("Could not find the application data directory")?;
+    let protected = protected::system_protection(registry.as_ref(), known_folders::default_resolver().as_ref(), &options);
+    let result = execute_plan(plan.clone(), backup, registry, journal, protected).await;
+    let (failed, errors, code) = deletion_outcome(result, items.len());
+
+    if json {
+        print_json(&ApplyReport { program: &plan.program, items: &items, errors })?;
+    } else {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        println!("Processed {} items for {}, {} could not be deleted.", items.len(), plan.program, failed);
+    }
+    Ok(code)
+}
+
+// How many of `total` items failed, why, and the exit code to report.
+fn deletion_outcome(result: Result<(), DeleteError>, total: usize) -> (usize, Vec<String>, i32) {
+    match result {
+        Ok(()) => (0, vec![], EXIT_SUCCESS),
+        Err(DeleteError::NothingDeleted(e)) => (total, vec![e], EXIT_NOTHING_DELETED),
+        Err(DeleteError::Failed { failed, errors }) => (failed, errors, EXIT_PARTIAL_FAILURE),
+    }
+}
+
+async fn trace_finish(registry: Arc<dyn RegistryBackend>, json: bool) -> Result<i32, String> {
+    let recorded = finish_trace(trace_roots(known_folders::default_resolver().as_ref()), manifest_store()?, registry).await?;
+    if json {
+        print_json(&recorded)?;
+    } else {
+        let rows: Vec<[String; 4]> = recorded
+            .iter()
+            .map(|m: &InstallManifest| {
+                [m.program.clone(), m.directories.len().to_string(), m.files.len().to_string(), m.registry_keys.len().to_string()]
+            })
+            .collect();
+        print_table(["PROGRAM", "FOLDERS", "FILES", "REGISTRY KEYS"], &rows);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn manifest_store() -> Result<ManifestStore, String> {
+    ManifestStore::default_location().ok_or_else(|| "Could not find the application data directory".to_string())
+}
+
+// Scans like the GUI does, flagging items other installed programs use as well.
+async fn find_leftovers(program: &ProgramInfo, registry: Arc<dyn RegistryBackend>, options: ScanOptions) -> Result<Vec<Leftover>, String> {
+    let installed = load_installed_programs(registry.clone()).await?;
+    let (folders, rules) = (known_folders::default_resolver(), Arc::new(load_rules().await?));
+    let mut items = scan_for_leftovers(program.clone(), registry, manifest_store()?, folders, rules, options, ScanControl::default()).await?;
+    scan::mark_shared(&mut items, program, &installed);
+    Ok(items)
+}
+
+// Mirrors the GUI: unless asked for everything, only high-confidence matches are deleted.
+fn select(leftovers: Vec<Leftover>, all: bool) -> Vec<Leftover> {
+    leftovers.into_iter().filter(|l| all || l.is_preselected()).collect()
+}
+
+// Exact (case-insensitive) name match wins; otherwise the query must pick out a single program.
+async fn find_program(registry: Arc<dyn RegistryBackend>, query: &str) -> Result<ProgramInfo, String> {
+    let programs = load_installed_programs(registry).await?;
+    let query_lower = query.to_lowercase();
+
+    if let Some(exact) = programs.iter().find(|p| p.name.to_lowercase() == query_lower) {
+        return Ok(exact.clone());
+    }
+    let matches: Vec<&ProgramInfo> = programs.iter().filter(|p| p.name.to_lowercase().contains(&query_lower)).collect();
+    match matches.as_slice() {
+        [single] => Ok((*single).clone()),
+        [] => Err(format!("No installed program matches \"{}\"", query)),
+        many => Err(format!(
+            "\"{}\" matches several programs: {}",
+            query,
+            many.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+// --- Output ---
+
+#[derive(Serialize)]
+struct ScanReport<'a> {
+    program: &'a ProgramInfo,
+    items: &'a [Leftover],
+}
+
+#[derive(Serialize)]
+struct UninstallReport<'a> {
+    program: &'a ProgramInfo,
+    uninstall: &'a UninstallOutcome,
+    items: &'a [Leftover],
+}
+
+#[derive(Serialize)]
+struct CleanReport<'a> {
+    program: &'a ProgramInfo,
+    items: &'a [Leftover],
+    errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ApplyReport<'a> {
+    program: &'a str,
+    items: &'a [FoundItem],
+    errors: Vec<String>,
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn print_leftovers(items: &[Leftover]) {
+    let now = chrono::Local::now();
+    let rows: Vec<[String; 8]> = items
+        .iter()
+        .map(|leftover| {
+            let (kind, path) = match &leftover.item {
+                FoundItem::File(path) => ("File", path.display().to_string()),
+                FoundItem::Directory(path) => ("Folder", path.display().to_string()),
+                FoundItem::RegistryKey(key) => ("Registry", key.clone()),
+                FoundItem::RegistryValue { key, name } => ("Value", format!("{}\\{}", key, name)),
+            };
+            let user = leftover.user.clone().unwrap_or_else(|| "-".to_string());
+            let (size, modified, owner) = match (&leftover.metadata, &leftover.key_metadata) {
+                (Some(m), _) => (m.size(), m.modified, m.owner.clone()),
+                (_, Some(k)) => (k.size(), k.last_write, None),
+                _ => ("-".to_string(), None, None),
+            };
+            let modified = modified.map_or("-".to_string(), |t| metadata::age(t, now));
+            let owner = owner.unwrap_or_else(|| "-".to_string());
+            let reason = match &leftover.shared_with {
+                Some(other) => format!("{}; also used by {}", leftover.reason, other),
+                None => leftover.reason.to_string(),
+            };
+            [kind.to_string(), format!("{}%", leftover.confidence), reason, user, size, modified, owner, path]
+        })
+        .collect();
+    print_table(["TYPE", "CONFIDENCE", "REASON", "USER", "SIZE", "MODIFIED", "OWNER", "PATH"], &rows);
+    println!("\nTotal: {} on disk", plan::format_size(scan::reclaimable_bytes(items.iter())));
+}
+
+fn print_table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) {
+    print!("{}", format_table(headers, rows));
+}
+
+fn format_table<const N: usize>(headers: [&str; N], rows: &[[String; N]]) -> String {
+    let mut widths = headers.map(|h| h.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| -> String {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", line.join("  ").trim_end())
+    };
+
+    let mut out = format_row(headers.to_vec());
+    for row in rows {
+        out.push_str(&format_row(row.iter().map(String::as_str).collect()));
+    }
+    out
+}
+
+fn confirm(prompt: &str) -> Result<bool, String> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).map_err(|e| e.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_commands_and_flags() {
+        assert_eq!(parse_args(&args(&["list", "--json"])).unwrap(), CliArgs { command: CliCommand::List, json: true, scan_options: ScanOptions::default(), overrides: Overrides::default() });
+        assert_eq!(
+            parse_args(&args(&["clean", "Contoso App", "--yes", "--backup"])).unwrap().command,
+            CliCommand::Clean { program: "Contoso App".into(), yes: true, backup: true, all: false, dry_run: false }
+        );
+        assert_eq!(
+            parse_args(&args(&["apply", "plan.json", "--yes"])).unwrap().command,
+            CliCommand::Apply { plan: "plan.json".into(), yes: true, backup: false }
+        );
+        assert_eq!(
+            parse_args(&args(&["uninstall", "Contoso", "--quiet"])).unwrap().command,
+            CliCommand::Uninstall { program: "Contoso".into(), quiet: true }
+        );
+        assert_eq!(parse_args(&args(&["trace", "finish"])).unwrap().command, CliCommand::TraceFinish);
+        assert_eq!(parse_args(&args(&["scan", "Contoso", "--signed-out-users"])).unwrap().overrides.include_signed_out_users, Some(true));
+        assert_eq!(parse_args(&args(&["scan", "Contoso", "--aggressive"])).unwrap().overrides.aggressive, Some(true));
+        assert_eq!(parse_args(&args(&["scan", "Contoso", "--no-aggressive"])).unwrap().overrides.aggressive, Some(false));
+        assert_eq!(parse_args(&args(&["scan", "Contoso"])).unwrap().overrides, Overrides::default());
+        assert_eq!(
+            parse_args(&args(&["scan", "--profiles-root", "/home", "Contoso"])).unwrap().scan_options.profiles_root,
+            Some(PathBuf::from("/home"))
+        );
+        assert!(parse_args(&args(&["scan", "Contoso", "--profiles-root"])).is_err());
+        assert_eq!(parse_args(&args(&["scan", "Contoso", "--max-depth", "8"])).unwrap().overrides.max_depth, Some(8));
+        assert!(parse_args(&args(&["scan", "Contoso", "--max-depth", "deep"])).is_err());
+        assert_eq!(
+            parse_args(&args(&["clean", "Contoso", "--protect", r"D:\Work", "--protect", r"HKEY_CURRENT_USER\Software\Shared"]))
+                .unwrap()
+                .scan_options
+                .protected,
+            [r"D:\Work", r"HKEY_CURRENT_USER\Software\Shared"]
+        );
+        assert!(parse_args(&args(&["trace"])).is_err());
+        assert!(parse_args(&args(&["scan"])).is_err());
+        assert!(parse_args(&args(&["scan", "a", "b"])).is_err());
+        assert!(parse_args(&args(&["list", "--force"])).is_err());
+        assert!(parse_args(&args(&["clean", "Contoso", "--json"])).is_err());
+        assert!(parse_args(&args(&["apply", "plan.json", "--json"])).is_err());
+        assert!(parse_args(&args(&["clean", "Contoso", "--json", "--dry-run"])).is_ok());
+    }
+
+    #[test]
+    fn counts_failed_items_rather_than_lines() {
+        let refused = DeleteError::NothingDeleted("Refusing to delete protected items:\na\nb".into());
+        assert_eq!(deletion_outcome(Err(refused), 5), (5, vec!["Refusing to delete protected items:\na\nb".into()], EXIT_NOTHING_DELETED));
+        let failed = DeleteError::Failed { failed: 1, errors: vec!["Failed to delete a:\naccess denied".into()] };
+        assert_eq!(deletion_outcome(Err(failed), 5).0, 1);
+        assert_eq!(deletion_outcome(Ok(()), 5), (0, vec![], EXIT_SUCCESS));
+    }
+
+    #[test]
+    fn formats_aligned_table() {
+        let table = format_table(["NAME", "VERSION"], &[["Contoso".into(), "1.0".into()], ["A".into(), "".into()]]);
+        assert_eq!(table, "NAME     VERSION\nContoso  1.0\nA\n");
+    }
+}
@@ -0,0 +1,223 @@
+use crate::profiles;
+use crate::scan::{ScanOptions, DEFAULT_MAX_DEPTH};
+use crate::FoundItem;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// --- Config ---
+
+// Settings kept between runs, e.g.
+//
+//     backup_registry = true
+//     extra_roots = ['D:\Games']
+//     exclude_paths = ['%LOCALAPPDATA%\Temp\**', '**\*.log']
+//     exclude_keys = ['\\Software\\Contoso\\Licensing$']
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub backup_registry: bool,
+    pub include_signed_out_users: bool,
+    pub max_depth: usize,
+    // Substring matching, as in `--aggressive`.
+    pub aggressive: bool,
+    // Folders searched on top of the known folders and every profile's AppData.
+    pub extra_roots: Vec<PathBuf>,
+    // Globs (with `%VAR%` references) for files and folders never reported; excluding a
+    // folder excludes everything in it.
+    pub exclude_paths: Vec<String>,
+    // Case-insensitive regular expressions for registry keys never reported.
+    pub exclude_keys: Vec<String>,
+    // Folders and registry keys never found or deleted, on top of the built-in list.
+    pub protected: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backup_registry: true,
+            include_signed_out_users: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            aggressive: false,
+            extra_roots: Vec::new(),
+            exclude_paths: Vec::new(),
+            exclude_keys: Vec::new(),
+            protected: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), String> {
+        Exclusions::new(&self.exclude_paths, &self.exclude_keys).map(|_| ())
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        self.merge_into(ScanOptions::default(), &Overrides::default())
+    }
+
+    // Settings given on the command line win over saved ones; lists are combined.
+    pub fn merge_into(&self, mut options: ScanOptions, overrides: &Overrides) -> ScanOptions {
+        options.include_signed_out_users = overrides.include_signed_out_users.unwrap_or(self.include_signed_out_users);
+        options.aggressive = overrides.aggressive.unwrap_or(self.aggressive);
+        options.max_depth = overrides.max_depth.unwrap_or(self.max_depth);
+        options.extra_roots.extend(self.extra_roots.iter().cloned());
+        options.exclude_paths.extend(self.exclude_paths.iter().cloned());
+        options.exclude_keys.extend(self.exclude_keys.iter().cloned());
+        options.protected.extend(self.protected.iter().cloned());
+        options
+    }
+}
+
+// Settings given on the command line; `None` keeps the saved one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub include_signed_out_users: Option<bool>,
+    pub aggressive: Option<bool>,
+    pub max_depth: Option<usize>,
+}
+
+// --- Config Store ---
+
+#[derive(Debug, Clone)]
+pub struct ConfigStore {
+    path: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        ConfigStore { path: path.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|d| ConfigStore::at(d.join("KuriUninstaller").join("config.toml")))
+    }
+
+    // The defaults until a config has been saved.
+    pub fn load(&self) -> Result<Config, String> {
+        if !self.path.exists() {
+            return Ok(Config::default());
+        }
+        let text = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        let config: Config =
+            toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", self.path.display(), e))?;
+        config.validate().map_err(|e| format!("Invalid config file {}: {}", self.path.display(), e))?;
+        Ok(config)
+    }
+
+    pub fn save(&self, config: &Config) -> Result<(), String> {
+        config.validate()?;
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let text = toml::to_string_pretty(config).map_err(|e| e.to_string())?;
+        fs::write(&self.path, text).map_err(|e| e.to_string())
+    }
+}
+
+// --- Exclusions ---
+
+// Compiled `exclude_paths` and `exclude_keys`.
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    paths: Vec<glob::Pattern>,
+    keys: Vec<Regex>,
+}
+
+impl Exclusions {
+    pub fn new(paths: &[String], keys: &[String]) -> Result<Self, String> {
+        let paths = paths
+            .iter()
+            .map(|p| glob::Pattern::new(&profiles::expand_env(p)).map_err(|e| format!("Invalid path pattern `{}`: {}", p, e)))
+            .collect::<Result<_, _>>()?;
+        let keys = keys
+            .iter()
+            .map(|k| {
+                RegexBuilder::new(k)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| format!("Invalid registry pattern `{}`: {}", k, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Exclusions { paths, keys })
+    }
+
+    pub fn excludes(&self, item: &FoundItem) -> bool {
+        let options = glob::MatchOptions { case_sensitive: false, ..Default::default() };
+        match item {
+            FoundItem::File(path) | FoundItem::Directory(path) => path
+                .ancestors()
+                .any(|path| self.paths.iter().any(|pattern| pattern.matches_path_with(path, options))),
+            FoundItem::RegistryKey(key) => self.keys.iter().any(|re| re.is_match(key)),
+            // Either the key alone or the key with the value name appended.
+            FoundItem::RegistryValue { key, name } => {
+                let value = format!("{}\\{}", key, name);
+                self.keys.iter().any(|re| re.is_match(key) || re.is_match(&value))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_bad_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ConfigStore::at(dir.path().join("KuriUninstaller").join("config.toml"));
+        assert_eq!(store.load().unwrap(), Config::default());
+
+        let config = Config {
+            backup_registry: false,
+            max_depth: 3,
+            extra_roots: vec![PathBuf::from("/games")],
+            exclude_keys: vec![r"\\Licensing$".into()],
+            ..Default::default()
+        };
+        store.save(&config).unwrap();
+        assert_eq!(store.load().unwrap(), config);
+
+        let bad = Config { exclude_keys: vec!["(".into()], ..Default::default() };
+        assert!(store.save(&bad).unwrap_err().contains("Invalid registry pattern"));
+        fs::write(dir.path().join("KuriUninstaller").join("config.toml"), "max_dpeth = 3").unwrap();
+        assert!(store.load().is_err());
+    }
+
+    #[test]
+    fn command_line_settings_win_over_saved_ones() {
+        let config = Config { aggressive: true, max_depth: 3, extra_roots: vec![PathBuf::from("/games")], ..Default::default() };
+        let saved = config.merge_into(ScanOptions::default(), &Overrides::default());
+        assert!(saved.aggressive);
+        assert_eq!(saved.max_depth, 3);
+
+        // Even when they match the defaults.
+        let overrides = Overrides { aggressive: Some(false), max_depth: Some(DEFAULT_MAX_DEPTH), ..Default::default() };
+        let given = ScanOptions { extra_roots: vec![PathBuf::from("/more")], ..Default::default() };
+        let options = config.merge_into(given, &overrides);
+        assert!(!options.aggressive);
+        assert_eq!(options.max_depth, DEFAULT_MAX_DEPTH);
+        assert_eq!(options.extra_roots, [PathBuf::from("/more"), PathBuf::from("/games")]);
+    }
+
+    #[test]
+    fn excludes_matching_items_and_their_contents() {
+        let exclusions = Exclusions::new(
+            &["/data/**/cache".into(), "*.log".into()],
+            &[r"\\Software\\Contoso\\Licensing$".into()],
+        )
+        .unwrap();
+
+        assert!(exclusions.excludes(&FoundItem::Directory(PathBuf::from("/data/Contoso/Cache"))));
+        assert!(exclusions.excludes(&FoundItem::File(PathBuf::from("/data/Contoso/cache/a.bin"))));
+        assert!(exclusions.excludes(&FoundItem::File(PathBuf::from("/tmp/contoso.log"))));
+        assert!(!exclusions.excludes(&FoundItem::Directory(PathBuf::from("/data/Contoso"))));
+        assert!(exclusions.excludes(&FoundItem::RegistryKey(r"HKEY_CURRENT_USER\SOFTWARE\Contoso\Licensing".into())));
+        assert!(!exclusions.excludes(&FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso\Licensing\Keys".into())));
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        let exclusions = Exclusions::new(&[], &[r"\\Run\\Contoso$".into()]).unwrap();
+        assert!(exclusions.excludes(&FoundItem::RegistryValue { key: run.into(), name: "Contoso".into() }));
+        assert!(!exclusions.excludes(&FoundItem::RegistryValue { key: run.into(), name: "Fabrikam".into() }));
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+// Older runs are dropped so registry snapshots don't make the journal grow forever.
+const MAX_RUNS: usize = 50;
+
+// --- Journal Records ---
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanupRun {
+    pub id: String,
+    pub program: String,
+    pub timestamp: String,
+    pub entries: Vec<JournalEntry>,
+    pub undone: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub item: JournalItem,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalItem {
+    // `trash_id` is the platform's identifier for the item once it is in the Recycle Bin.
+    Path { original_path: PathBuf, trash_id: Option<String> },
+    // `snapshot` is the key's full subtree in .reg format, taken just before deletion.
+    RegistryKey { path: String, snapshot: String },
+    // A single value, with its data in .reg format.
+    RegistryValue { key: String, name: String, snapshot: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    Pending,
+    Deleted,
+    Failed(String),
+    Restored,
+    RestoreFailed(String),
+}
+
+impl Outcome {
+    // Deleted, perhaps deleted before an interrupted run stopped, or not brought back by
+    // an earlier undo.
+    pub fn needs_restoring(&self) -> bool {
+        matches!(self, Outcome::Deleted | Outcome::Pending | Outcome::RestoreFailed(_))
+    }
+}
+
+impl CleanupRun {
+    pub fn new(program: &str) -> Self {
+        let now = chrono::Local::now();
+        CleanupRun {
+            id: now.format("%Y%m%d-%H%M%S%.3f").to_string(),
+            program: program.to_string(),
+            timestamp: now.to_rfc3339(),
+            entries: vec![],
+            undone: false,
+        }
+    }
+
+    pub fn push(&mut self, item: JournalItem, outcome: Outcome) {
+        self.entries.push(JournalEntry { item, outcome });
+    }
+}
+
+// --- Persistence ---
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Journal { path: path.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|d| Journal::at(d.join("KuriUninstaller").join("journal.json")))
+    }
+
+    pub fn load(&self) -> Result<Vec<CleanupRun>, String> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let contents = fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&contents).map_err(|e| format!("Corrupt journal {}: {}", self.path.display(), e))
+    }
+
+    // Inserts the run, or replaces the stored copy if a run with the same id exists.
+    pub fn record(&self, run: &CleanupRun) -> Result<(), String> {
+        let mut runs = self.load()?;
+        match runs.iter_mut().find(|r| r.id == run.id) {
+            Some(existing) => *existing = run.clone(),
+            None => runs.push(run.clone()),
+        }
+        if runs.len() > MAX_RUNS {
+            runs.drain(..runs.len() - MAX_RUNS);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        // Write to a sibling file first so a crash never leaves a half-written journal.
+        let tmp = self.path.with_extension("json.tmp");
+        let contents = serde_json::to_string_pretty(&runs).map_err(|e| e.to_string())?;
+        fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &self.path).map_err(|e| e.to_string())
+    }
+
+    pub fn last_undoable(&self) -> Result<Option<CleanupRun>, String> {
+        Ok(self.load()?.into_iter().rev().find(|r| {
+            !r.undone && r.entries.iter().any(|e| e.outcome.needs_restoring())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_and_replaces_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("nested").join("journal.json"));
+        assert_eq!(journal.load().unwrap(), vec![]);
+
+        let mut run = CleanupRun::new("Contoso");
+        run.push(JournalItem::Path { original_path: PathBuf::from("a.txt"), trash_id: None }, Outcome::Pending);
+        journal.record(&run).unwrap();
+        // Interrupted before anything was marked deleted, but still undoable.
+        assert_eq!(journal.last_undoable().unwrap(), Some(run.clone()));
+
+        run.entries[0].outcome = Outcome::Deleted;
+        journal.record(&run).unwrap();
+
+        assert_eq!(journal.load().unwrap(), vec![run.clone()]);
+        assert_eq!(journal.last_undoable().unwrap(), Some(run.clone()));
+
+        run.undone = true;
+        journal.record(&run).unwrap();
+        assert_eq!(journal.last_undoable().unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("journal.json"));
+        for i in 0..MAX_RUNS + 3 {
+            let mut run = CleanupRun::new("Contoso");
+            run.id = format!("{:03}", i);
+            journal.record(&run).unwrap();
+        }
+
+        let runs = journal.load().unwrap();
+        assert_eq!(runs.len(), MAX_RUNS);
+        assert_eq!(runs[0].id, "003");
+    }
+}
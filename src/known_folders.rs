@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// --- Known Folders ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownFolder {
+    LocalAppData,
+    RoamingAppData,
+    ProgramData,
+    ProgramFiles,
+    ProgramFilesX86,
+    CommonFiles,
+    CommonFilesX86,
+    StartMenu,
+    CommonStartMenu,
+    PublicDesktop,
+    Temp,
+}
+
+impl KnownFolder {
+    pub const ALL: [KnownFolder; 11] = [
+        KnownFolder::LocalAppData,
+        KnownFolder::RoamingAppData,
+        KnownFolder::ProgramData,
+        KnownFolder::ProgramFiles,
+        KnownFolder::ProgramFilesX86,
+        KnownFolder::CommonFiles,
+        KnownFolder::CommonFilesX86,
+        KnownFolder::StartMenu,
+        KnownFolder::CommonStartMenu,
+        KnownFolder::PublicDesktop,
+        KnownFolder::Temp,
+    ];
+
+    // The folder a variable such as `%LOCALAPPDATA%` or `%ProgramFiles(x86)%` names.
+    pub fn from_variable(name: &str) -> Option<KnownFolder> {
+        KnownFolder::ALL.into_iter().find(|folder| {
+            let (var, below) = folder.env_location();
+            below.is_empty() && var.eq_ignore_ascii_case(name)
+        })
+    }
+
+    // The environment variable Windows sets for the folder, and the path below it.
+    fn env_location(self) -> (&'static str, &'static [&'static str]) {
+        const START_MENU: &[&str] = &["Microsoft", "Windows", "Start Menu"];
+        match self {
+            KnownFolder::LocalAppData => ("LOCALAPPDATA", &[]),
+            KnownFolder::RoamingAppData => ("APPDATA", &[]),
+            KnownFolder::ProgramData => ("ProgramData", &[]),
+            KnownFolder::ProgramFiles => ("ProgramFiles", &[]),
+            KnownFolder::ProgramFilesX86 => ("ProgramFiles(x86)", &[]),
+            KnownFolder::CommonFiles => ("CommonProgramFiles", &[]),
+            KnownFolder::CommonFilesX86 => ("CommonProgramFiles(x86)", &[]),
+            KnownFolder::StartMenu => ("APPDATA", START_MENU),
+            KnownFolder::CommonStartMenu => ("ProgramData", START_MENU),
+            KnownFolder::PublicDesktop => ("PUBLIC", &["Desktop"]),
+            KnownFolder::Temp => ("TEMP", &[]),
+        }
+    }
+}
+
+pub trait KnownFolders: Send + Sync {
+    fn resolve(&self, folder: KnownFolder) -> Option<PathBuf>;
+
+    // Every known folder that exists, without duplicates (on 32-bit Windows the x86
+    // folders are the same as the native ones).
+    fn existing(&self) -> Vec<PathBuf> {
+        let mut folders: Vec<PathBuf> = Vec::new();
+        for path in KnownFolder::ALL.iter().filter_map(|&f| self.resolve(f)) {
+            if path.is_dir() && !folders.contains(&path) {
+                folders.push(path);
+            }
+        }
+        folders
+    }
+}
+
+#[cfg(windows)]
+pub fn default_resolver() -> Arc<dyn KnownFolders> {
+    Arc::new(ShellKnownFolders)
+}
+
+#[cfg(not(windows))]
+pub fn default_resolver() -> Arc<dyn KnownFolders> {
+    Arc::new(EnvKnownFolders::from_env())
+}
+
+// --- Environment Implementation ---
+
+// Resolves folders from the variables Windows defines for them. A fixed set of variables
+// can stand in for the real environment, e.g. from configuration or in tests.
+#[derive(Debug, Clone, Default)]
+pub struct EnvKnownFolders {
+    // Keyed by lowercase name; Windows variable names are case-insensitive.
+    vars: HashMap<String, String>,
+}
+
+impl EnvKnownFolders {
+    pub fn from_env() -> Self {
+        EnvKnownFolders::from_vars(std::env::vars())
+    }
+
+    pub fn from_vars<K: Into<String>, V: Into<String>>(vars: impl IntoIterator<Item = (K, V)>) -> Self {
+        let vars = vars.into_iter().map(|(k, v)| (k.into().to_lowercase(), v.into())).collect();
+        EnvKnownFolders { vars }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(&name.to_lowercase()).filter(|v| !v.is_empty()).cloned()
+    }
+}
+
+impl KnownFolders for EnvKnownFolders {
+    fn resolve(&self, folder: KnownFolder) -> Option<PathBuf> {
+        let (var, below) = folder.env_location();
+        let base = self.var(var).or_else(|| match folder {
+            KnownFolder::Temp => self.var("TMP"),
+            _ => None,
+        })?;
+        Some(below.iter().fold(PathBuf::from(base), |path, part| path.join(part)))
+    }
+}
+
+// --- Windows Implementation ---
+
+// Asks the shell, which also knows about folders redirected by policy.
+#[cfg(windows)]
+pub struct ShellKnownFolders;
+
+#[cfg(windows)]
+impl KnownFolders for ShellKnownFolders {
+    fn resolve(&self, folder: KnownFolder) -> Option<PathBuf> {
+        use std::os::windows::ffi::OsStringExt;
+        use windows_sys::Win32::System::Com::CoTaskMemFree;
+        use windows_sys::Win32::UI::Shell::*;
+
+        let id = match folder {
+            KnownFolder::LocalAppData => FOLDERID_LocalAppData,
+            KnownFolder::RoamingAppData => FOLDERID_RoamingAppData,
+            KnownFolder::ProgramData => FOLDERID_ProgramData,
+            KnownFolder::ProgramFiles => FOLDERID_ProgramFiles,
+            KnownFolder::ProgramFilesX86 => FOLDERID_ProgramFilesX86,
+            KnownFolder::CommonFiles => FOLDERID_ProgramFilesCommon,
+            KnownFolder::CommonFilesX86 => FOLDERID_ProgramFilesCommonX86,
+            KnownFolder::StartMenu => FOLDERID_StartMenu,
+            KnownFolder::CommonStartMenu => FOLDERID_CommonStartMenu,
+            KnownFolder::PublicDesktop => FOLDERID_PublicDesktop,
+            // Temp isn't a known folder; it only exists as a variable.
+            KnownFolder::Temp => return EnvKnownFolders::from_env().resolve(folder),
+        };
+
+        let mut raw = std::ptr::null_mut();
+        let result = unsafe { SHGetKnownFolderPath(&id, 0, 0, &mut raw) };
+        let path = (result >= 0).then(|| unsafe {
+            let len = (0..).take_while(|&i| *raw.add(i) != 0).count();
+            PathBuf::from(std::ffi::OsString::from_wide(std::slice::from_raw_parts(raw, len)))
+        });
+        // The buffer must be freed even when the call fails.
+        unsafe { CoTaskMemFree(raw as *const _) };
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_folders_from_variables() {
+        let folders = EnvKnownFolders::from_vars([
+            ("PROGRAMDATA", r"C:\ProgramData"),
+            ("APPDATA", r"C:\Users\alice\AppData\Roaming"),
+            ("PUBLIC", r"C:\Users\Public"),
+            ("TMP", r"C:\Temp"),
+            ("ProgramFiles(x86)", ""),
+        ]);
+
+        let path = |parts: &[&str]| parts.iter().collect::<PathBuf>();
+        assert_eq!(folders.resolve(KnownFolder::ProgramData), Some(path(&[r"C:\ProgramData"])));
+        assert_eq!(
+            folders.resolve(KnownFolder::CommonStartMenu),
+            Some(path(&[r"C:\ProgramData", "Microsoft", "Windows", "Start Menu"]))
+        );
+        assert_eq!(
+            folders.resolve(KnownFolder::StartMenu),
+            Some(path(&[r"C:\Users\alice\AppData\Roaming", "Microsoft", "Windows", "Start Menu"]))
+        );
+        assert_eq!(folders.resolve(KnownFolder::PublicDesktop), Some(path(&[r"C:\Users\Public", "Desktop"])));
+        assert_eq!(folders.resolve(KnownFolder::Temp), Some(path(&[r"C:\Temp"])));
+        assert_eq!(folders.resolve(KnownFolder::ProgramFilesX86), None);
+        assert_eq!(folders.resolve(KnownFolder::ProgramFiles), None);
+    }
+
+    #[test]
+    fn lists_each_existing_folder_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_files = dir.path().join("Program Files");
+        std::fs::create_dir_all(&program_files).unwrap();
+        let program_files = program_files.to_string_lossy().into_owned();
+
+        let folders = EnvKnownFolders::from_vars([
+            ("ProgramFiles", program_files.as_str()),
+            ("ProgramFiles(x86)", program_files.as_str()),
+            ("ProgramData", &*dir.path().join("missing").to_string_lossy()),
+        ]);
+        assert_eq!(folders.existing(), [dir.path().join("Program Files")]);
+    }
+}
@@ -1,19 +1,52 @@
+mod cli;
+mod config;
+mod journal;
+mod known_folders;
+mod metadata;
+mod plan;
+mod profiles;
+mod protected;
+mod regfile;
+mod registry;
+mod rules;
+mod scan;
 mod theme;
+mod trace;
+mod uninstall;
 
 use iced::{
-    widget::{button, checkbox, column, container, scrollable, text, Space},
-    executor, Application, Command, Element, Length, Settings, Size,
+    futures::SinkExt,
+    subscription,
+    widget::{button, checkbox, column, container, scrollable, text, text_editor, text_input, Space},
+    executor, Application, Command, Element, Length, Settings, Size, Subscription,
 };
+use std::collections::BTreeSet;
 use std::fs;
-use std::io::Write;
 use std::path::PathBuf;
-use winreg::enums::*;
-use winreg::RegKey;
-use is_elevated;
+use std::sync::Arc;
+use config::{Config, ConfigStore};
+use journal::{CleanupRun, Journal, JournalItem, Outcome};
+use known_folders::KnownFolders;
+use plan::DeletionPlan;
+use regfile::RegOperation;
+use registry::RegistryBackend;
+use rules::{RuleSet, RuleStore};
+use profiles::MountedHives;
+use protected::ProtectedItems;
+use scan::{scan_for_leftovers, Leftover, ScanControl, ScanEvent, ScanOptions, ScanProgress};
+use serde::Serialize;
+use trace::{InstallManifest, ManifestStore, Snapshot};
+use uninstall::UninstallOutcome;
 
 // Entry point
 pub fn main() -> iced::Result {
-    if !is_elevated::is_elevated() {
+    // Any arguments switch to the headless command-line interface.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
+    if !is_elevated() {
         eprintln!("\n[ERROR] Administrator Privileges Required");
         eprintln!("This application needs to be run as an administrator to delete system-wide files and registry keys.");
         eprintln!("Please right-click the executable and select 'Run as administrator'.\n");
@@ -37,20 +70,42 @@ pub fn main() -> iced::Result {
     })
 }
 
+// Only Windows has system-wide registry keys that need an Administrator to remove;
+// elsewhere the current user's files are all Kuri touches.
+#[cfg(windows)]
+fn is_elevated() -> bool {
+    is_elevated::is_elevated()
+}
+
+#[cfg(not(windows))]
+fn is_elevated() -> bool {
+    true
+}
+
 // --- Data Structures ---
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 struct ProgramInfo {
     name: String,
     version: String,
     install_location: Option<PathBuf>,
+    publisher: Option<String>,
+    display_icon: Option<String>,
+    uninstall_string: Option<String>,
+    quiet_uninstall_string: Option<String>,
+    install_source: Option<PathBuf>,
+    // Full path of the program's Uninstall entry; its last component is often a product code.
+    registry_key: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "path", rename_all = "snake_case")]
 enum FoundItem {
     File(PathBuf),
     Directory(PathBuf),
     RegistryKey(String),
+    // A single value inside a key that otherwise stays, e.g. a `Run` entry.
+    RegistryValue { key: String, name: String },
 }
 
 impl std::fmt::Display for FoundItem {
@@ -59,6 +114,28 @@ impl std::fmt::Display for FoundItem {
             FoundItem::File(path) => write!(f, "[File] {}", path.display()),
             FoundItem::Directory(path) => write!(f, "[Folder] {}", path.display()),
             FoundItem::RegistryKey(key) => write!(f, "[Registry] {}", key),
+            FoundItem::RegistryValue { key, name } => {
+                write!(f, "[Registry Value] {}\\{}", key, if name.is_empty() { "(Default)" } else { name })
+            }
+        }
+    }
+}
+
+// Why a deletion stopped short.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DeleteError {
+    // Refused or failed before anything was touched (protected items, a failed backup,
+    // a plan that no longer matches).
+    NothingDeleted(String),
+    // `failed` items could not be deleted; the rest were.
+    Failed { failed: usize, errors: Vec<String> },
+}
+
+impl std::fmt::Display for DeleteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeleteError::NothingDeleted(e) => write!(f, "{}", e),
+            DeleteError::Failed { errors, .. } => write!(f, "{}", errors.join("\n")),
         }
     }
 }
@@ -67,19 +144,132 @@ impl std::fmt::Display for FoundItem {
 enum ViewState {
     #[default]
     ProgramList,
+    Uninstalling,
     Scanning,
     ScanResults,
     ConfirmingDelete,
+    PlanPreview,
     Deleting,
+    BackupList,
+    Restoring,
+    Undoing,
+    TakingSnapshot,
+    AwaitingInstall,
+    TraceFinished,
+    Settings,
 }
 
 struct KuriUninstaller {
     programs: Vec<ProgramInfo>,
     selected_program: Option<ProgramInfo>,
-    scan_results: Vec<(FoundItem, bool)>,
+    scan_results: Vec<(Leftover, bool)>,
+    // Result of the vendor uninstaller when the scan followed "Uninstall then Scan".
+    uninstall_outcome: Option<UninstallOutcome>,
+    backups: Vec<PathBuf>,
+    plan: Option<(DeletionPlan, PathBuf)>,
     view_state: ViewState,
     error_message: Option<String>,
     backup_registry: bool,
+    registry: Arc<dyn RegistryBackend>,
+    known_folders: Arc<dyn KnownFolders>,
+    // Per-program rules, consulted before name matching.
+    rules: Arc<RuleSet>,
+    // Saved settings; `scan_options` and `backup_registry` start out from these.
+    config_store: ConfigStore,
+    config: Config,
+    settings: Option<SettingsForm>,
+    journal: Journal,
+    manifests: ManifestStore,
+    traced: Vec<InstallManifest>,
+    scan_options: ScanOptions,
+    scan: ScanState,
+    next_scan_id: u64,
+    // Registry results whose value preview is expanded.
+    expanded_keys: BTreeSet<String>,
+}
+
+// Results are shown while the scan is still running, so the results view needs to know
+// whether more may come.
+#[derive(Default)]
+enum ScanState {
+    #[default]
+    Idle,
+    Running(Box<RunningScan>),
+    Cancelled,
+    Complete,
+}
+
+// A scan in progress. It runs inside a subscription keyed by `id`, so it keeps running across
+// updates and is dropped as soon as the scan stops running.
+struct RunningScan {
+    id: u64,
+    program: ProgramInfo,
+    control: ScanControl,
+    progress: ScanProgress,
+}
+
+// The settings screen edits a copy of the config; lists take one entry per line.
+struct SettingsForm {
+    extra_roots: text_editor::Content,
+    exclude_paths: text_editor::Content,
+    exclude_keys: text_editor::Content,
+    protected: text_editor::Content,
+    backup_registry: bool,
+    include_signed_out_users: bool,
+    aggressive: bool,
+    max_depth: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SettingsList {
+    ExtraRoots,
+    ExcludePaths,
+    ExcludeKeys,
+    Protected,
+}
+
+impl SettingsForm {
+    fn new(config: &Config) -> Self {
+        let lines = |entries: Vec<String>| text_editor::Content::with_text(&entries.join("\n"));
+        SettingsForm {
+            extra_roots: lines(config.extra_roots.iter().map(|p| p.display().to_string()).collect()),
+            exclude_paths: lines(config.exclude_paths.clone()),
+            exclude_keys: lines(config.exclude_keys.clone()),
+            protected: lines(config.protected.clone()),
+            backup_registry: config.backup_registry,
+            include_signed_out_users: config.include_signed_out_users,
+            aggressive: config.aggressive,
+            max_depth: config.max_depth.to_string(),
+        }
+    }
+
+    fn list_mut(&mut self, list: SettingsList) -> &mut text_editor::Content {
+        match list {
+            SettingsList::ExtraRoots => &mut self.extra_roots,
+            SettingsList::ExcludePaths => &mut self.exclude_paths,
+            SettingsList::ExcludeKeys => &mut self.exclude_keys,
+            SettingsList::Protected => &mut self.protected,
+        }
+    }
+
+    fn to_config(&self) -> Result<Config, String> {
+        let lines = |content: &text_editor::Content| -> Vec<String> {
+            content.text().lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect()
+        };
+        let max_depth = self.max_depth.trim().parse().map_err(|_| "Search depth must be a whole number".to_string())?;
+        let config = Config {
+            backup_registry: self.backup_registry,
+            include_signed_out_users: self.include_signed_out_users,
+            max_depth,
+            aggressive: self.aggressive,
+            extra_roots: lines(&self.extra_roots).into_iter().map(PathBuf::from).collect(),
+            exclude_paths: lines(&self.exclude_paths),
+            exclude_keys: lines(&self.exclude_keys),
+            protected: lines(&self.protected),
+        };
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 // --- Messages for UI interaction ---
@@ -87,18 +277,49 @@ struct KuriUninstaller {
 #[derive(Debug, Clone)]
 enum Message {
     LoadPrograms(Result<Vec<ProgramInfo>, String>),
+    RulesLoaded(Result<RuleSet, String>),
+    ConfigLoaded(Result<Config, String>),
     ProgramSelected(ProgramInfo),
     ScanButtonPressed,
-    ScanCompleted(Result<Vec<FoundItem>, String>),
+    SignedOutUsersToggled(bool),
+    UninstallButtonPressed,
+    UninstallCompleted(Result<UninstallOutcome, String>),
+    ScanProgressed(ScanProgress),
+    LeftoversFound(Vec<Leftover>),
+    CancelScanPressed,
+    ScanCompleted(Result<Vec<Leftover>, String>),
     ResultChecked(usize, bool),
+    KeyPreviewToggled(String),
     SelectAll,
     DeselectAll,
     DeleteSelectedButtonPressed,
     ConfirmDelete,
     CancelDelete,
+    DryRunPressed,
+    PlanReady(Result<(DeletionPlan, PathBuf), String>),
+    ExecutePlan,
     BackupCheckboxToggled(bool),
-    DeleteCompleted(Result<(), String>),
+    DeleteCompleted(Result<(), DeleteError>),
     BackButtonPressed,
+    RestoreButtonPressed,
+    BackupsLoaded(Result<Vec<PathBuf>, String>),
+    RestoreBackupSelected(PathBuf),
+    RestoreCompleted(Result<(), String>),
+    UndoButtonPressed,
+    UndoCompleted(Result<(), String>),
+    TraceButtonPressed,
+    TraceStarted(Result<(), String>),
+    FinishTracePressed,
+    CancelTracePressed,
+    TraceCompleted(Result<Vec<InstallManifest>, String>),
+    SettingsButtonPressed,
+    SettingsListEdited(SettingsList, text_editor::Action),
+    SettingsBackupToggled(bool),
+    SettingsSignedOutToggled(bool),
+    SettingsAggressiveToggled(bool),
+    SettingsDepthChanged(String),
+    SaveSettingsPressed,
+    SettingsSaved(Result<Config, String>),
     DismissError,
 }
 
@@ -111,16 +332,41 @@ impl Application for KuriUninstaller {
     type Flags = ();
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
+        let registry = registry::default_backend();
+        let manifests = ManifestStore::default_location().unwrap_or_else(|| ManifestStore::at("manifests"));
+        // A trace started before a restart picks up where it left off.
+        let view_state = if manifests.has_pending_snapshot() { ViewState::AwaitingInstall } else { ViewState::default() };
+        let config_store = ConfigStore::default_location().unwrap_or_else(|| ConfigStore::at("config.toml"));
         (
             KuriUninstaller {
                 programs: vec![],
                 selected_program: None,
                 scan_results: vec![],
-                view_state: ViewState::default(),
+                uninstall_outcome: None,
+                backups: vec![],
+                plan: None,
+                view_state,
                 error_message: None,
                 backup_registry: true,
+                registry: registry.clone(),
+                known_folders: known_folders::default_resolver(),
+                rules: Arc::new(RuleSet::default()),
+                config_store: config_store.clone(),
+                config: Config::default(),
+                settings: None,
+                journal: Journal::default_location().unwrap_or_else(|| Journal::at("journal.json")),
+                manifests,
+                traced: vec![],
+                scan_options: ScanOptions::default(),
+                scan: ScanState::Idle,
+                next_scan_id: 0,
+                expanded_keys: BTreeSet::new(),
             },
-            Command::perform(load_installed_programs(), Message::LoadPrograms),
+            Command::batch([
+                Command::perform(load_installed_programs(registry), Message::LoadPrograms),
+                Command::perform(load_rules(), Message::RulesLoaded),
+                Command::perform(load_config(config_store), Message::ConfigLoaded),
+            ]),
         )
     }
 
@@ -136,18 +382,82 @@ impl Application for KuriUninstaller {
         match message {
             Message::LoadPrograms(Ok(programs)) => self.programs = programs,
             Message::LoadPrograms(Err(e)) => self.error_message = Some(format!("Failed to load programs: {}", e)),
+            Message::RulesLoaded(Ok(rules)) => self.rules = Arc::new(rules),
+            Message::RulesLoaded(Err(e)) => self.error_message = Some(format!("Failed to load rules: {}", e)),
+            Message::ConfigLoaded(Ok(config)) => self.apply_config(config),
+            Message::ConfigLoaded(Err(e)) => self.error_message = Some(format!("Failed to load settings: {}", e)),
             Message::ProgramSelected(program) => self.selected_program = Some(program),
             Message::ScanButtonPressed => {
                 if let Some(program) = self.selected_program.clone() {
-                    self.view_state = ViewState::Scanning;
-                    return Command::perform(scan_for_leftovers(program), Message::ScanCompleted);
+                    self.uninstall_outcome = None;
+                    self.start_scan(program);
+                }
+            }
+            Message::SignedOutUsersToggled(include) => self.scan_options.include_signed_out_users = include,
+            Message::UninstallButtonPressed => {
+                if let Some(program) = &self.selected_program {
+                    match uninstall::uninstall_command(program, false) {
+                        Ok(command) => {
+                            self.view_state = ViewState::Uninstalling;
+                            return Command::perform(uninstall::run_uninstaller(command), Message::UninstallCompleted);
+                        }
+                        Err(e) => self.error_message = Some(e),
+                    }
                 }
             }
-            Message::ScanCompleted(Ok(results)) => {
-                self.scan_results = results.into_iter().map(|item| (item, true)).collect();
+            // Only a successful uninstall moves on to the scan; otherwise the program is
+            // likely still installed and its own files would show up as leftovers.
+            Message::UninstallCompleted(Ok(outcome)) => {
+                if let Some(program) = self.selected_program.clone().filter(|_| outcome.succeeded()) {
+                    self.uninstall_outcome = Some(outcome);
+                    self.start_scan(program);
+                    return Command::none();
+                }
+                self.error_message = Some(outcome.describe());
+                self.view_state = ViewState::ProgramList;
+            }
+            Message::UninstallCompleted(Err(e)) => {
+                self.error_message = Some(e);
+                self.view_state = ViewState::ProgramList;
+            }
+            Message::ScanProgressed(progress) => {
+                if let ScanState::Running(scan) = &mut self.scan {
+                    scan.progress = progress;
+                }
+            }
+            // Messages from a scan that was cancelled may still be on their way.
+            Message::LeftoversFound(_) | Message::ScanCompleted(_) if !matches!(self.scan, ScanState::Running(_)) => {}
+            Message::LeftoversFound(mut found) => {
+                self.mark_shared(&mut found);
+                self.merge_results(found);
+                self.view_state = ViewState::ScanResults;
+            }
+            Message::CancelScanPressed => {
+                self.stop_scan(ScanState::Cancelled);
+                // Whatever was found so far stays up for review.
+                if self.scan_results.is_empty() {
+                    self.view_state = ViewState::ProgramList;
+                }
+            }
+            Message::ScanCompleted(Ok(mut results)) => {
+                self.mark_shared(&mut results);
+                self.scan = ScanState::Complete;
+                // The final list is deduplicated and sorted; keep what the user already ticked.
+                let reviewed = std::mem::take(&mut self.scan_results);
+                self.scan_results = results
+                    .into_iter()
+                    .map(|leftover| {
+                        let checked = reviewed
+                            .iter()
+                            .find(|(seen, _)| seen.item == leftover.item)
+                            .map_or(leftover.is_preselected(), |(_, checked)| *checked);
+                        (leftover, checked)
+                    })
+                    .collect();
                 self.view_state = ViewState::ScanResults;
             }
             Message::ScanCompleted(Err(e)) => {
+                self.scan = ScanState::Idle;
                 self.error_message = Some(format!("Error during scan: {}", e));
                 self.view_state = ViewState::ProgramList;
             }
@@ -156,51 +466,228 @@ impl Application for KuriUninstaller {
                     item.1 = is_checked;
                 }
             }
+            Message::KeyPreviewToggled(key) => {
+                if !self.expanded_keys.remove(&key) {
+                    self.expanded_keys.insert(key);
+                }
+            }
             Message::SelectAll => self.scan_results.iter_mut().for_each(|(_, c)| *c = true),
             Message::DeselectAll => self.scan_results.iter_mut().for_each(|(_, c)| *c = false),
             Message::DeleteSelectedButtonPressed => self.view_state = ViewState::ConfirmingDelete,
             Message::BackupCheckboxToggled(is_checked) => self.backup_registry = is_checked,
             Message::ConfirmDelete => {
                 self.view_state = ViewState::Deleting;
-                let items_to_delete: Vec<FoundItem> = self
-                    .scan_results
-                    .iter()
-                    .filter(|(_, c)| *c)
-                    .map(|(item, _)| item.clone())
-                    .collect();
+                let items_to_delete = self.checked_items();
+                let program = self.selected_program.as_ref().map(|p| p.name.clone()).unwrap_or_default();
                 return Command::perform(
-                    delete_items(items_to_delete, self.backup_registry),
+                    delete_items(
+                        program,
+                        items_to_delete,
+                        self.backup_registry,
+                        self.registry.clone(),
+                        self.journal.clone(),
+                        self.protection(),
+                    ),
                     Message::DeleteCompleted,
                 );
             }
-            Message::CancelDelete => self.view_state = ViewState::ScanResults,
+            Message::CancelDelete => {
+                self.view_state = ViewState::ScanResults;
+                self.plan = None;
+            }
+            Message::DryRunPressed => {
+                let program = self.selected_program.as_ref().map(|p| p.name.clone()).unwrap_or_default();
+                return Command::perform(
+                    create_plan(program, self.checked_items(), self.registry.clone()),
+                    Message::PlanReady,
+                );
+            }
+            Message::PlanReady(Ok(plan)) => {
+                self.plan = Some(plan);
+                self.view_state = ViewState::PlanPreview;
+            }
+            Message::PlanReady(Err(e)) => self.error_message = Some(format!("Failed to create plan: {}", e)),
+            Message::ExecutePlan => {
+                if let Some((plan, _)) = self.plan.take() {
+                    self.view_state = ViewState::Deleting;
+                    return Command::perform(
+                        execute_plan(
+                            plan,
+                            self.backup_registry,
+                            self.registry.clone(),
+                            self.journal.clone(),
+                            self.protection(),
+                        ),
+                        Message::DeleteCompleted,
+                    );
+                }
+            }
             Message::BackButtonPressed => {
+                self.stop_scan(ScanState::Idle);
                 self.view_state = ViewState::ProgramList;
                 self.selected_program = None;
                 self.scan_results = vec![];
+                self.backups = vec![];
+                self.traced = vec![];
+                self.settings = None;
             }
             Message::DeleteCompleted(Ok(())) => {
                 self.view_state = ViewState::ProgramList;
                 self.selected_program = None;
                 self.scan_results = vec![];
-                return Command::perform(load_installed_programs(), Message::LoadPrograms);
+                return Command::perform(load_installed_programs(self.registry.clone()), Message::LoadPrograms);
             }
             Message::DeleteCompleted(Err(e)) => {
                 self.error_message = Some(format!("An error occurred: {}", e));
                 self.view_state = ViewState::ScanResults;
             }
+            Message::RestoreButtonPressed => {
+                return Command::perform(list_backups(), Message::BackupsLoaded);
+            }
+            Message::BackupsLoaded(Ok(backups)) => {
+                self.backups = backups;
+                self.view_state = ViewState::BackupList;
+            }
+            Message::BackupsLoaded(Err(e)) => self.error_message = Some(format!("Failed to list backups: {}", e)),
+            Message::RestoreBackupSelected(path) => {
+                self.view_state = ViewState::Restoring;
+                return Command::perform(restore_backup(path, self.registry.clone()), Message::RestoreCompleted);
+            }
+            Message::RestoreCompleted(Ok(())) => {
+                self.view_state = ViewState::ProgramList;
+                self.backups = vec![];
+                return Command::perform(load_installed_programs(self.registry.clone()), Message::LoadPrograms);
+            }
+            Message::RestoreCompleted(Err(e)) => {
+                self.error_message = Some(format!("Failed to restore backup: {}", e));
+                self.view_state = ViewState::BackupList;
+            }
+            Message::UndoButtonPressed => {
+                self.view_state = ViewState::Undoing;
+                return Command::perform(
+                    undo_last_cleanup(self.journal.clone(), self.registry.clone()),
+                    Message::UndoCompleted,
+                );
+            }
+            Message::UndoCompleted(Ok(())) => {
+                self.view_state = ViewState::ProgramList;
+                return Command::perform(load_installed_programs(self.registry.clone()), Message::LoadPrograms);
+            }
+            Message::UndoCompleted(Err(e)) => {
+                self.error_message = Some(format!("Failed to undo cleanup: {}", e));
+                self.view_state = ViewState::ProgramList;
+            }
+            Message::TraceButtonPressed => {
+                self.view_state = ViewState::TakingSnapshot;
+                return Command::perform(
+                    start_trace(trace_roots(self.known_folders.as_ref()), self.manifests.clone(), self.registry.clone()),
+                    Message::TraceStarted,
+                );
+            }
+            Message::TraceStarted(Ok(())) => self.view_state = ViewState::AwaitingInstall,
+            Message::TraceStarted(Err(e)) => {
+                self.error_message = Some(format!("Failed to take snapshot: {}", e));
+                self.view_state = ViewState::ProgramList;
+            }
+            Message::FinishTracePressed => {
+                self.view_state = ViewState::TakingSnapshot;
+                return Command::perform(
+                    finish_trace(trace_roots(self.known_folders.as_ref()), self.manifests.clone(), self.registry.clone()),
+                    Message::TraceCompleted,
+                );
+            }
+            Message::CancelTracePressed => match self.manifests.discard_snapshot() {
+                Ok(()) => self.view_state = ViewState::ProgramList,
+                Err(e) => self.error_message = Some(format!("Failed to cancel trace: {}", e)),
+            },
+            Message::TraceCompleted(Ok(manifests)) => {
+                self.traced = manifests;
+                self.view_state = ViewState::TraceFinished;
+                return Command::perform(load_installed_programs(self.registry.clone()), Message::LoadPrograms);
+            }
+            Message::TraceCompleted(Err(e)) => {
+                self.error_message = Some(format!("Failed to finish trace: {}", e));
+                self.view_state = ViewState::AwaitingInstall;
+            }
+            Message::SettingsButtonPressed => {
+                self.settings = Some(SettingsForm::new(&self.config));
+                self.view_state = ViewState::Settings;
+            }
+            Message::SettingsListEdited(list, action) => {
+                if let Some(form) = &mut self.settings {
+                    form.list_mut(list).perform(action);
+                }
+            }
+            Message::SettingsBackupToggled(checked) => {
+                if let Some(form) = &mut self.settings {
+                    form.backup_registry = checked;
+                }
+            }
+            Message::SettingsSignedOutToggled(checked) => {
+                if let Some(form) = &mut self.settings {
+                    form.include_signed_out_users = checked;
+                }
+            }
+            Message::SettingsAggressiveToggled(checked) => {
+                if let Some(form) = &mut self.settings {
+                    form.aggressive = checked;
+                }
+            }
+            Message::SettingsDepthChanged(depth) => {
+                if let Some(form) = &mut self.settings {
+                    form.max_depth = depth;
+                }
+            }
+            Message::SaveSettingsPressed => {
+                if let Some(form) = &self.settings {
+                    match form.to_config() {
+                        Ok(config) => return Command::perform(save_config(self.config_store.clone(), config), Message::SettingsSaved),
+                        Err(e) => self.error_message = Some(e),
+                    }
+                }
+            }
+            Message::SettingsSaved(Ok(config)) => {
+                self.apply_config(config);
+                self.settings = None;
+                self.view_state = ViewState::ProgramList;
+            }
+            Message::SettingsSaved(Err(e)) => self.error_message = Some(format!("Failed to save settings: {}", e)),
             Message::DismissError => self.error_message = None,
         }
         Command::none()
     }
 
-    fn view(&self) -> Element<Message, Self::Theme> {
+    fn subscription(&self) -> Subscription<Message> {
+        let ScanState::Running(scan) = &self.scan else { return Subscription::none() };
+        let (program, registry, manifests, folders, rules, options) = (
+            scan.program.clone(),
+            self.registry.clone(),
+            self.manifests.clone(),
+            self.known_folders.clone(),
+            self.rules.clone(),
+            self.scan_options.clone(),
+        );
+        scan_subscription(scan.id, scan.control.clone(), move |control| {
+            scan_for_leftovers(program, registry, manifests, folders, rules, options, control)
+        })
+    }
+
+    fn view(&self) -> Element<'_, Message, Self::Theme> {
         let main_content = match self.view_state {
             ViewState::ProgramList => self.view_program_list(),
+            ViewState::Uninstalling => self.view_loading("Waiting for the uninstaller to finish..."),
             ViewState::Scanning => self.view_loading("Scanning..."),
             ViewState::ScanResults => self.view_scan_results(),
             ViewState::ConfirmingDelete => self.view_confirm_delete(),
+            ViewState::PlanPreview => self.view_plan(),
             ViewState::Deleting => self.view_loading("Deleting items..."),
+            ViewState::BackupList => self.view_backup_list(),
+            ViewState::Restoring => self.view_loading("Restoring backup..."),
+            ViewState::Undoing => self.view_loading("Undoing last cleanup..."),
+            ViewState::TakingSnapshot => self.view_loading("Taking a snapshot of files and registry..."),
+            ViewState::AwaitingInstall => self.view_awaiting_install(),
+            ViewState::TraceFinished => self.view_trace_finished(),
+            ViewState::Settings => self.view_settings(),
         };
 
         let content = if let Some(error) = &self.error_message {
@@ -235,11 +722,82 @@ impl Application for KuriUninstaller {
 // --- UI Views ---
 
 impl KuriUninstaller {
-    fn view_loading(&self, message: &str) -> Element<Message, theme::Fluent> {
+    fn start_scan(&mut self, program: ProgramInfo) {
+        self.next_scan_id += 1;
+        self.scan = ScanState::Running(Box::new(RunningScan {
+            id: self.next_scan_id,
+            program,
+            control: ScanControl::default(),
+            progress: ScanProgress::default(),
+        }));
+        self.scan_results = vec![];
+        self.expanded_keys.clear();
+        self.view_state = ViewState::Scanning;
+    }
+
+    fn stop_scan(&mut self, state: ScanState) {
+        if let ScanState::Running(scan) = std::mem::replace(&mut self.scan, state) {
+            scan.control.cancel();
+        }
+    }
+
+    // Items another installed program also uses stay unchecked.
+    fn mark_shared(&self, results: &mut [Leftover]) {
+        if let ScanState::Running(scan) = &self.scan {
+            scan::mark_shared(results, &scan.program, &self.programs);
+        }
+    }
+
+    // The same item can be reached from several roots; keep its strongest match, and
+    // leave the order alone so nothing moves under the user's cursor.
+    fn merge_results(&mut self, found: Vec<Leftover>) {
+        for leftover in found {
+            match self.scan_results.iter_mut().find(|(seen, _)| seen.item == leftover.item) {
+                Some((seen, _)) if seen.confidence < leftover.confidence => *seen = leftover,
+                Some(_) => {}
+                None => {
+                    let checked = leftover.is_preselected();
+                    self.scan_results.push((leftover, checked));
+                }
+            }
+        }
+    }
+
+    fn checked_items(&self) -> Vec<FoundItem> {
+        self.scan_results
+            .iter()
+            .filter(|(_, c)| *c)
+            .map(|(leftover, _)| leftover.item.clone())
+            .collect()
+    }
+
+    // Session toggles such as the signed-out users checkbox start from the saved settings again.
+    fn apply_config(&mut self, config: Config) {
+        self.scan_options = config.scan_options();
+        self.backup_registry = config.backup_registry;
+        self.config = config;
+    }
+
+    fn protection(&self) -> ProtectedItems {
+        protected::system_protection(self.registry.as_ref(), self.known_folders.as_ref(), &self.scan_options)
+    }
+
+    fn view_loading(&self, message: &str) -> Element<'_, Message, theme::Fluent> {
+        // A running scan shows where it is and can be stopped; everything else just waits.
+        let detail = match &self.scan {
+            ScanState::Running(scan) if matches!(self.view_state, ViewState::Scanning) => column![
+                text(scan_progress_text(&scan.progress)).size(20),
+                text(&scan.progress.current).size(14),
+                button(text("Cancel")).style(theme::Button::Secondary).on_press(Message::CancelScanPressed).padding(10),
+            ]
+            .align_items(iced::Alignment::Center)
+            .spacing(10),
+            _ => column![text("Please wait.").size(20)],
+        };
         column![
             Space::with_height(Length::Fill),
             text(message).size(32),
-            text("Please wait.").size(20),
+            detail,
             Space::with_height(Length::Fill),
         ]
         .align_items(iced::Alignment::Center)
@@ -247,7 +805,7 @@ impl KuriUninstaller {
         .into()
     }
 
-    fn view_program_list(&self) -> Element<Message, theme::Fluent> {
+    fn view_program_list(&self) -> Element<'_, Message, theme::Fluent> {
         let program_list = self.programs.iter().fold(column![].spacing(5), |col, program| {
             let program_clone = program.clone();
             let is_selected = self.selected_program.as_ref() == Some(program);
@@ -265,34 +823,112 @@ impl KuriUninstaller {
             scan_button // Disabled
         };
 
+        let uninstall_button = button(text("Uninstall then Scan")).style(theme::Button::Secondary).padding(10);
+        let uninstall_button = if self.selected_program.as_ref().is_some_and(|p| p.uninstall_string.is_some() || p.quiet_uninstall_string.is_some()) {
+            uninstall_button.on_press(Message::UninstallButtonPressed)
+        } else {
+            uninstall_button // Disabled
+        };
+
+        let restore_button = button(text("Restore Backup")).style(theme::Button::Secondary)
+            .on_press(Message::RestoreButtonPressed).padding(10);
+        let undo_button = button(text("Undo Last Cleanup")).style(theme::Button::Secondary)
+            .on_press(Message::UndoButtonPressed).padding(10);
+        let signed_out_checkbox = checkbox("Include signed-out users' registry", self.scan_options.include_signed_out_users)
+            .on_toggle(Message::SignedOutUsersToggled);
+        let trace_button = button(text("Trace Install")).style(theme::Button::Secondary)
+            .on_press(Message::TraceButtonPressed).padding(10);
+        let settings_button = button(text("Settings")).style(theme::Button::Secondary)
+            .on_press(Message::SettingsButtonPressed).padding(10);
+
         column![
             text("Installed Programs").size(32),
             text(self.selected_program.as_ref().map_or("Select a program to scan", |p| &p.name)).size(20),
             Space::with_height(Length::Fixed(15.0)),
             container(scrollable(program_list)).height(Length::Fill),
-            Space::with_height(Length::Fixed(15.0)),
-            scan_button,
+            signed_out_checkbox,
+            iced::widget::row![settings_button, trace_button, undo_button, restore_button, uninstall_button, scan_button].spacing(10),
         ]
         .spacing(20)
         .align_items(iced::Alignment::Center)
         .into()
     }
 
-    fn view_scan_results(&self) -> Element<Message, theme::Fluent> {
-        let results_list = self.scan_results.iter().enumerate().fold(
+    fn view_scan_results(&self) -> Element<'_, Message, theme::Fluent> {
+        // Machine-wide items first, then one group per user profile.
+        let mut groups: Vec<Option<&String>> = self.scan_results.iter().map(|(l, _)| l.user.as_ref()).collect();
+        groups.sort();
+        groups.dedup();
+        let ordered = groups.iter().flat_map(|group| {
+            self.scan_results.iter().enumerate().filter(move |(_, (l, _))| l.user.as_ref() == *group)
+        });
+
+        let now = chrono::Local::now();
+        let mut current_group = None;
+        let results_list = ordered.fold(
             column![].spacing(5),
-            |col, (i, (item, is_checked))| {
-                let checkbox = checkbox(item.to_string(), *is_checked)
-                    .on_toggle(move |checked| Message::ResultChecked(i, checked));
-                col.push(checkbox)
+            |col, (i, (leftover, is_checked))| {
+                let col = if current_group != Some(leftover.user.as_ref()) {
+                    current_group = Some(leftover.user.as_ref());
+                    let header = leftover.user.as_ref().map_or("Machine-wide".to_string(), |user| format!("User: {}", user));
+                    col.push(text(header).size(18))
+                } else {
+                    col
+                };
+                let checkbox = checkbox(leftover.item.to_string(), *is_checked)
+                    .on_toggle(move |checked| Message::ResultChecked(i, checked))
+                    .width(Length::Fill);
+                let confidence = text(format!("{}% · {}", leftover.confidence, leftover.reason))
+                    .size(14)
+                    .style(theme::Text::confidence(leftover.confidence));
+                let col = col.push(iced::widget::row![checkbox, confidence].spacing(10));
+                let col = match &leftover.shared_with {
+                    Some(other) => col.push(text(format!("Also used by {}", other)).size(12).style(theme::Text::Error)),
+                    None => col,
+                };
+                match (&leftover.item, &leftover.metadata, &leftover.key_metadata) {
+                    // Something written to this week is probably still in use.
+                    (_, Some(metadata), _) if metadata.recently_modified(now) => col.push(
+                        text(format!("{} · may still be in use", metadata.summary(now)))
+                            .size(12)
+                            .style(theme::Text::Error),
+                    ),
+                    (_, Some(metadata), _) => col.push(text(metadata.summary(now)).size(12)),
+                    (FoundItem::RegistryKey(key), _, Some(details)) => {
+                        let expanded = self.expanded_keys.contains(key);
+                        let summary = text(details.summary(now)).size(12);
+                        let col = if details.preview.is_empty() {
+                            col.push(summary)
+                        } else {
+                            let toggle = button(text(if expanded { "Hide values" } else { "Show values" }).size(12))
+                                .style(theme::Button::Secondary)
+                                .on_press(Message::KeyPreviewToggled(key.clone()))
+                                .padding(2);
+                            col.push(iced::widget::row![summary, toggle].spacing(10).align_items(iced::Alignment::Center))
+                        };
+                        if !expanded {
+                            return col;
+                        }
+                        details.preview.iter().fold(col, |col, (name, data)| {
+                            col.push(text(format!("    {} = {}", name, data)).size(12))
+                        })
+                    }
+                    (FoundItem::RegistryValue { .. }, _, _) => match &leftover.value_data {
+                        Some(data) => col.push(text(format!("= {}", data)).size(12)),
+                        None => col,
+                    },
+                    _ => col,
+                }
             },
         );
 
         let back_button = button(text("Back to List")).style(theme::Button::Secondary)
             .on_press(Message::BackButtonPressed).padding(10);
             
+        // Items can be reviewed while the scan runs, but not deleted until it's done.
+        let running = matches!(self.scan, ScanState::Running(_));
         let delete_button = button(text("Delete Selected")).style(theme::Button::Primary).padding(10);
-        let delete_button = if self.scan_results.iter().any(|(_, checked)| *checked) {
+        let delete_button = if !running && self.scan_results.iter().any(|(_, checked)| *checked) {
             delete_button.on_press(Message::DeleteSelectedButtonPressed)
         } else {
             delete_button // Disabled
@@ -304,10 +940,26 @@ impl KuriUninstaller {
             .on_press(Message::DeselectAll).padding(5);
 
         let title = text(format!("Scan Results for {}", self.selected_program.as_ref().unwrap().name)).size(32);
+        let status = match &self.scan {
+            ScanState::Running(scan) => format!("Still scanning: {}.", scan_progress_text(&scan.progress)),
+            ScanState::Cancelled => format!("Scan cancelled; showing the {} items found before it stopped.", self.scan_results.len()),
+            _ => format!("Found {} items.", self.scan_results.len()),
+        };
+        let status = format!("{} {} to be reclaimed.", status, plan::format_size(scan::reclaimable_bytes(self.scan_results.iter().filter(|(_, c)| *c).map(|(l, _)| l))));
+        let scan_status = iced::widget::row![text(status).size(16)].spacing(10).align_items(iced::Alignment::Center);
+        let scan_status = if running {
+            scan_status.push(button(text("Cancel")).style(theme::Button::Secondary).on_press(Message::CancelScanPressed).padding(5))
+        } else {
+            scan_status
+        };
 
         column![
             title,
-            text(format!("Found {} items. Uncheck items to keep them.", self.scan_results.len())).size(16),
+            text(format!(
+                "{}Only high-confidence matches are checked; review the rest before selecting them.",
+                self.uninstall_outcome.as_ref().map_or(String::new(), |o| format!("{}. ", o.describe())),
+            )).size(16),
+            scan_status,
             iced::widget::row![select_all_button, deselect_all_button].spacing(10),
             Space::with_height(Length::Fixed(10.0)),
             container(scrollable(results_list)).height(Length::Fill),
@@ -319,19 +971,173 @@ impl KuriUninstaller {
         .into()
     }
 
-    fn view_confirm_delete(&self) -> Element<Message, theme::Fluent> {
+    fn view_backup_list(&self) -> Element<'_, Message, theme::Fluent> {
+        let backup_list = self.backups.iter().fold(column![].spacing(5), |col, path| {
+            let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+            let button = button(text(name))
+                .on_press(Message::RestoreBackupSelected(path.clone()))
+                .style(theme::Button::Secondary)
+                .width(Length::Fill);
+            col.push(button)
+        });
+
+        let subtitle = if self.backups.is_empty() {
+            "No registry backups found."
+        } else {
+            "Select a backup to re-import it into the registry."
+        };
+
+        let back_button = button(text("Back to List")).style(theme::Button::Secondary)
+            .on_press(Message::BackButtonPressed).padding(10);
+
+        column![
+            text("Registry Backups").size(32),
+            text(subtitle).size(16),
+            Space::with_height(Length::Fixed(15.0)),
+            container(scrollable(backup_list)).height(Length::Fill),
+            Space::with_height(Length::Fixed(15.0)),
+            back_button,
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn view_plan(&self) -> Element<'_, Message, theme::Fluent> {
+        let Some((plan, path)) = &self.plan else {
+            return self.view_loading("Building plan...");
+        };
+
+        let plan_list = plan.lines().into_iter().fold(column![].spacing(5), |col, line| col.push(text(line).size(14)));
+
+        let back_button = button(text("Back")).style(theme::Button::Secondary)
+            .on_press(Message::CancelDelete).padding(10);
+        let execute_button = button(text("Execute Plan")).style(theme::Button::Primary)
+            .on_press(Message::ExecutePlan).padding(10);
+
+        column![
+            text(format!("Deletion Plan for {}", plan.program)).size(32),
+            text(format!("Nothing has been deleted. The plan was saved to {}", path.display())).size(16),
+            Space::with_height(Length::Fixed(10.0)),
+            container(scrollable(plan_list)).height(Length::Fill),
+            Space::with_height(Length::Fixed(15.0)),
+            iced::widget::row![back_button, Space::with_width(Length::Fill), execute_button].spacing(10),
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn view_awaiting_install(&self) -> Element<'_, Message, theme::Fluent> {
+        let cancel_button = button(text("Cancel Trace")).style(theme::Button::Secondary)
+            .on_press(Message::CancelTracePressed).padding(10);
+        let finish_button = button(text("Finish Trace")).style(theme::Button::Primary)
+            .on_press(Message::FinishTracePressed).padding(10);
+
+        column![
+            Space::with_height(Length::Fill),
+            text("Tracing Install").size(32),
+            text("A snapshot has been taken. Run the installer now, and click Finish Trace once it has completed.").size(16),
+            text("Everything the installer creates will be recorded and used as the leftover list for that program.").size(16),
+            iced::widget::row![cancel_button, finish_button].spacing(10),
+            Space::with_height(Length::Fill),
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn view_trace_finished(&self) -> Element<'_, Message, theme::Fluent> {
+        let manifest_list = self.traced.iter().fold(column![].spacing(10), |col, manifest| {
+            col.push(text(format!(
+                "{}: {} folders, {} files, {} registry keys",
+                manifest.program,
+                manifest.directories.len(),
+                manifest.files.len(),
+                manifest.registry_keys.len()
+            )).size(16))
+        });
+        let back_button = button(text("Back to List")).style(theme::Button::Secondary)
+            .on_press(Message::BackButtonPressed).padding(10);
+
+        column![
+            text("Install Recorded").size(32),
+            text("Scans of these programs will now use the recorded changes instead of name matching.").size(16),
+            container(scrollable(manifest_list)).height(Length::Fill),
+            back_button,
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn view_settings(&self) -> Element<'_, Message, theme::Fluent> {
+        let Some(form) = &self.settings else {
+            return self.view_loading("Loading settings...");
+        };
+
+        let fields = column![
+            checkbox("Back up registry keys to a .reg file before deleting", form.backup_registry)
+                .on_toggle(Message::SettingsBackupToggled),
+            checkbox("Include signed-out users' registry", form.include_signed_out_users)
+                .on_toggle(Message::SettingsSignedOutToggled),
+            checkbox("Aggressive matching: also match names inside other words (more false positives)", form.aggressive)
+                .on_toggle(Message::SettingsAggressiveToggled),
+            column![
+                text("Search depth").size(18),
+                text("How many folder levels below each search location are looked at.").size(14),
+                text_input("8", &form.max_depth).on_input(Message::SettingsDepthChanged).padding(5),
+            ]
+            .spacing(5),
+            settings_list("Extra search locations", "Folders searched on top of the usual ones, e.g. D:\\Games.", &form.extra_roots, SettingsList::ExtraRoots),
+            settings_list(
+                "Excluded files and folders",
+                "Glob patterns such as %LOCALAPPDATA%\\Temp\\** or **\\*.log; everything inside an excluded folder is skipped.",
+                &form.exclude_paths,
+                SettingsList::ExcludePaths,
+            ),
+            settings_list(
+                "Excluded registry keys",
+                "Regular expressions matched against the full key path, ignoring case.",
+                &form.exclude_keys,
+                SettingsList::ExcludeKeys,
+            ),
+            settings_list("Protected locations", "Folders and registry keys that are never found or deleted.", &form.protected, SettingsList::Protected),
+        ]
+        .spacing(15)
+        .padding(5);
+
+        let back_button = button(text("Cancel")).style(theme::Button::Secondary)
+            .on_press(Message::BackButtonPressed).padding(10);
+        let save_button = button(text("Save")).style(theme::Button::Primary)
+            .on_press(Message::SaveSettingsPressed).padding(10);
+
+        column![
+            text("Settings").size(32),
+            text("One entry per line. Changes apply to the next scan.").size(16),
+            container(scrollable(fields)).height(Length::Fill),
+            iced::widget::row![back_button, Space::with_width(Length::Fill), save_button].spacing(10),
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn view_confirm_delete(&self) -> Element<'_, Message, theme::Fluent> {
         let items_to_delete_count = self.scan_results.iter().filter(|(_, checked)| *checked).count();
 
         let confirmation_text = text(format!("Are you sure you want to delete {} selected items?", items_to_delete_count)).size(24);
         let warning_text = text("Files will be moved to the Recycle Bin, but registry keys will be permanently deleted.").size(16);
         
-        let backup_checkbox = checkbox("Create a log of registry keys to be deleted", self.backup_registry)
+        let backup_checkbox = checkbox("Back up registry keys to a .reg file before deleting", self.backup_registry)
             .on_toggle(Message::BackupCheckboxToggled);
 
         let confirm_button = button(text("Yes, Delete Them")).style(theme::Button::Primary)
             .on_press(Message::ConfirmDelete).padding(10);
         let cancel_button = button(text("Cancel")).style(theme::Button::Secondary)
             .on_press(Message::CancelDelete).padding(10);
+        let dry_run_button = button(text("Dry Run")).style(theme::Button::Secondary)
+            .on_press(Message::DryRunPressed).padding(10);
 
         column![
             Space::with_height(Length::Fill),
@@ -340,7 +1146,7 @@ impl KuriUninstaller {
             Space::with_height(Length::Fixed(15.0)),
             backup_checkbox,
             Space::with_height(Length::Fixed(20.0)),
-            iced::widget::row![cancel_button, confirm_button].spacing(10),
+            iced::widget::row![cancel_button, dry_run_button, confirm_button].spacing(10),
             Space::with_height(Length::Fill),
         ]
         .spacing(20)
@@ -349,176 +1155,305 @@ impl KuriUninstaller {
     }
 }
 
+fn settings_list<'a>(
+    title: &'a str,
+    hint: &'a str,
+    content: &'a text_editor::Content,
+    list: SettingsList,
+) -> Element<'a, Message, theme::Fluent> {
+    column![
+        text(title).size(18),
+        text(hint).size(14),
+        text_editor(content)
+            .height(Length::Fixed(80.0))
+            .on_action(move |action| Message::SettingsListEdited(list, action)),
+    ]
+    .spacing(5)
+    .into()
+}
+
 // --- Core Logic Functions ---
 
-async fn load_installed_programs() -> Result<Vec<ProgramInfo>, String> {
+// The built-in rules plus the user's own from `%APPDATA%\KuriUninstaller\rules`.
+async fn load_rules() -> Result<RuleSet, String> {
+    match RuleStore::default_location() {
+        Some(store) => store.load(),
+        None => RuleSet::builtin(),
+    }
+}
+
+// Defaults until settings have been saved.
+async fn load_config(store: ConfigStore) -> Result<Config, String> {
+    store.load()
+}
+
+async fn save_config(store: ConfigStore, config: Config) -> Result<Config, String> {
+    store.save(&config)?;
+    Ok(config)
+}
+
+async fn load_installed_programs(registry: Arc<dyn RegistryBackend>) -> Result<Vec<ProgramInfo>, String> {
     let mut programs = Vec::new();
-    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
     let uninstall_paths = [
-        r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
-        r"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKEY_LOCAL_MACHINE\SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
     ];
 
     for path in uninstall_paths {
-        if let Ok(uninstall) = hklm.open_subkey(path) {
-            for key_name in uninstall.enum_keys().filter_map(Result::ok) {
-                if let Ok(subkey) = uninstall.open_subkey(&key_name) {
-                    if let Ok(name) = subkey.get_value::<String, _>("DisplayName") {
-                        if name.is_empty() { continue; }
-
-                        let version = subkey.get_value("DisplayVersion").unwrap_or_default();
-                        let install_location: Option<PathBuf> = subkey
-                            .get_value::<String, _>("InstallLocation")
-                            .ok()
-                            .filter(|s| !s.is_empty())
-                            .map(PathBuf::from);
-                        
-                        if !programs.iter().any(|p: &ProgramInfo| p.name == name) {
-                            programs.push(ProgramInfo { name, version, install_location });
-                        }
+        if let Ok(key_names) = registry.enum_keys(path) {
+            for key_name in key_names {
+                let subkey = registry::join(path, &key_name);
+                if let Some(name) = registry.get_string(&subkey, "DisplayName") {
+                    if name.is_empty() { continue; }
+
+                    let version = registry.get_string(&subkey, "DisplayVersion").unwrap_or_default();
+                    let non_empty = |value: &str| registry.get_string(&subkey, value).filter(|s| !s.trim().is_empty());
+
+                    if !programs.iter().any(|p: &ProgramInfo| p.name == name) {
+                        programs.push(ProgramInfo {
+                            name,
+                            version,
+                            install_location: non_empty("InstallLocation").map(PathBuf::from),
+                            publisher: non_empty("Publisher").map(|s| s.trim().to_string()),
+                            display_icon: non_empty("DisplayIcon"),
+                            uninstall_string: non_empty("UninstallString"),
+                            quiet_uninstall_string: non_empty("QuietUninstallString"),
+                            install_source: non_empty("InstallSource").map(PathBuf::from),
+                            registry_key: subkey.clone(),
+                        });
                     }
                 }
             }
         }
     }
 
-    programs.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    programs.sort_by_key(|p| p.name.to_lowercase());
     Ok(programs)
 }
 
-fn generate_search_terms(program: &ProgramInfo) -> Vec<String> {
-    let mut terms = vec![program.name.to_lowercase()];
-    terms.push(program.name.replace(' ', "").to_lowercase());
-
-    if let Some(location) = &program.install_location {
-        if let Some(folder_name) = location.file_name() {
-            terms.push(folder_name.to_string_lossy().to_lowercase());
-        }
+async fn delete_items(
+    program: String,
+    items: Vec<FoundItem>,
+    backup: bool,
+    registry: Arc<dyn RegistryBackend>,
+    journal: Journal,
+    protected: ProtectedItems,
+) -> Result<(), DeleteError> {
+    // Scans already leave these out; this catches plans and lists from anywhere else.
+    let refused: Vec<String> = items.iter().filter(|item| protected.is_protected(item)).map(|item| item.to_string()).collect();
+    if !refused.is_empty() {
+        return Err(DeleteError::NothingDeleted(format!(
+            "Refusing to delete protected items, so nothing was deleted:\n{}",
+            refused.join("\n")
+        )));
     }
-    
-    terms.dedup();
-    terms
-}
 
-async fn scan_for_leftovers(program: ProgramInfo) -> Result<Vec<FoundItem>, String> {
-    let mut results = Vec::new();
-    let search_terms = generate_search_terms(&program);
-
-    let mut search_dirs = vec![
-        dirs::data_local_dir(),
-        dirs::data_dir(),
-        dirs::config_dir(),
-    ]
-    .into_iter()
-    .filter_map(|p| p)
-    .collect::<Vec<_>>();
-    
-    if let Some(pd) = dirs::data_local_dir() { // Using data_local_dir as a stand-in for ProgramData
-        search_dirs.push(pd);
-    }
+    let mut errors = Vec::new();
+    let reg_keys_to_delete: Vec<_> = items
+        .iter()
+        .filter_map(|item| match item {
+            FoundItem::RegistryKey(key) => Some(key.clone()),
+            _
+ => None,
+        })
+        .collect();
+    let reg_values_to_delete: Vec<_> = items
+        .iter()
+        .filter_map(|item| match item {
+            FoundItem::RegistryValue { key, name } => Some((key.clone(), name.clone())),
+            _ => None,
+        })
+        .collect();
+    let _mounted = MountedHives::for_keys(registry.clone(), &registry_keys(&items));
 
-    if let Some(install_loc) = &program.install_location {
-        if install_loc.exists() {
-            search_dirs.push(install_loc.clone());
+    if backup && !(reg_keys_to_delete.is_empty() && reg_values_to_delete.is_empty()) {
+        // Deleting keys we could not back up would make the cleanup irreversible.
+        if let Err(e) = backup_registry_keys(registry.as_ref(), &reg_keys_to_delete, &reg_values_to_delete).await {
+            return Err(DeleteError::NothingDeleted(format!("Failed to back up registry keys, so nothing was deleted: {}", e)));
         }
     }
 
-    for dir in search_dirs {
-        for entry in walkdir::WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-            let entry_name = entry.file_name().to_string_lossy().to_lowercase();
-            if search_terms.iter().any(|term| entry_name.contains(term)) {
-                if entry.file_type().is_dir() {
-                    results.push(FoundItem::Directory(entry.path().to_path_buf()));
-                } else {
-                    results.push(FoundItem::File(entry.path().to_path_buf()));
+    // Record everything we are about to do before touching anything, so an interrupted
+    // run can still be undone.
+    let mut run = CleanupRun::new(&program);
+    for item in &items {
+        match item {
+            FoundItem::File(path) | FoundItem::Directory(path) => run.push(
+                JournalItem::Path { original_path: path.clone(), trash_id: None },
+                Outcome::Pending,
+            ),
+            FoundItem::RegistryKey(key_path) => match regfile::export_keys(registry.as_ref(), std::slice::from_ref(key_path)) {
+                Ok(snapshot) => run.push(
+                    JournalItem::RegistryKey { path: key_path.clone(), snapshot },
+                    Outcome::Pending,
+                ),
+                Err(e) => {
+                    let error = format!("Could not snapshot registry key {}: {}", key_path, e);
+                    errors.push(error.clone());
+                    run.push(
+                        JournalItem::RegistryKey { path: key_path.clone(), snapshot: String::new() },
+                        Outcome::Failed(error),
+                    );
+                }
+            },
+            FoundItem::RegistryValue { key, name } => {
+                let value = (key.clone(), name.clone());
+                match regfile::export(registry.as_ref(), &[], std::slice::from_ref(&value)) {
+                    Ok(snapshot) => run.push(
+                        JournalItem::RegistryValue { key: key.clone(), name: name.clone(), snapshot },
+                        Outcome::Pending,
+                    ),
+                    Err(e) => {
+                        let error = format!("Could not snapshot {}: {}", item, e);
+                        errors.push(error.clone());
+                        run.push(
+                            JournalItem::RegistryValue { key: key.clone(), name: name.clone(), snapshot: String::new() },
+                            Outcome::Failed(error),
+                        );
+                    }
                 }
             }
         }
     }
+    journal
+        .record(&run)
+        .map_err(|e| DeleteError::NothingDeleted(format!("Failed to write cleanup journal, so nothing was deleted: {}", e)))?;
 
-    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let reg_paths_to_scan = [
-        (&hklm, "SOFTWARE", "HKEY_LOCAL_MACHINE\\SOFTWARE"),
-        (&hklm, "SOFTWARE\\Wow6432Node", "HKEY_LOCAL_MACHINE\\SOFTWARE\\Wow6432Node"),
-        (&hkcu, "Software", "HKEY_CURRENT_USER\\Software"),
-    ];
-
-    for (hive, path_to_open, path_prefix_for_results) in &reg_paths_to_scan {
-        if let Ok(base_key) = hive.open_subkey(path_to_open) {
-            for subkey_name in base_key.enum_keys().filter_map(Result::ok) {
-                 if search_terms.iter().any(|term| subkey_name.to_lowercase().contains(term)) {
-                     results.push(FoundItem::RegistryKey(format!("{}\\{}", path_prefix_for_results, subkey_name)));
-                 }
+    for entry in run.entries.iter_mut().filter(|e| e.outcome == Outcome::Pending) {
+        let result = match &entry.item {
+            JournalItem::Path { original_path, .. } => trash::delete(original_path)
+                .map_err(|e| format!("Failed to delete {}: {}", original_path.display(), e)),
+            JournalItem::RegistryKey { path, .. } => registry
+                .delete_subtree(path)
+                .map_err(|e| format!("Failed to delete registry key {}: {}", path, e)),
+            JournalItem::RegistryValue { key, name, .. } => registry
+                .delete_value(key, name)
+                .map_err(|e| format!("Failed to delete registry value {}\\{}: {}", key, name, e)),
+        };
+        entry.outcome = match result {
+            Ok(()) => Outcome::Deleted,
+            Err(e) => {
+                errors.push(e.clone());
+                Outcome::Failed(e)
             }
-        }
+        };
+    }
+
+    locate_trashed_items(&mut run);
+    if let Err(e) = journal.record(&run) {
+        errors.push(format!("Failed to write cleanup journal: {}", e));
+    }
+
+    let failed = run.entries.iter().filter(|e| matches!(e.outcome, Outcome::Failed(_))).count();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(DeleteError::Failed { failed, errors })
+    }
+}
+
+// `trash::delete` doesn't say where an item ended up, so look up the most recently
+// deleted Recycle Bin entry for each path we trashed.
+fn locate_trashed_items(run: &mut CleanupRun) {
+    let trashed = run.entries.iter_mut().filter_map(|e| match (&mut e.item, &e.outcome) {
+        (JournalItem::Path { original_path, trash_id }, Outcome::Deleted) => Some((original_path, trash_id)),
+        _ => None,
+    });
+    let mut trashed = trashed.peekable();
+    if trashed.peek().is_none() {
+        return;
     }
-    
-    results.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
-    results.dedup();
+    let Ok(bin) = trash::os_limited::list() else { return };
 
-    Ok(results)
+    for (original_path, trash_id) in trashed {
+        *trash_id = bin
+            .iter()
+            .filter(|t| t.original_path() == *original_path)
+            .max_by_key(|t| t.time_deleted)
+            .map(|t| t.id.to_string_lossy().into_owned());
+    }
 }
 
-async fn delete_items(items: Vec<FoundItem>, backup: bool) -> Result<(), String> {
+async fn undo_last_cleanup(journal: Journal, registry: Arc<dyn RegistryBackend>) -> Result<(), String> {
+    let mut run = journal.last_undoable()?.ok_or("There is no cleanup to undo.")?;
     let mut errors = Vec::new();
-    let reg_keys_to_delete: Vec<_> = items
+
+    let keys: Vec<String> = run
+        .entries
         .iter()
-        .filter_map(|item| match item {
-            FoundItem::RegistryKey(key) => Some(key.clone()),
-            _
- => None,
+        .filter_map(|e| match &e.item {
+            JournalItem::RegistryKey { path, .. } => Some(path.clone()),
+            JournalItem::RegistryValue { key, .. } => Some(key.clone()),
+            JournalItem::Path { .. } => None,
         })
         .collect();
+    let _mounted = MountedHives::for_keys(registry.clone(), &keys);
 
-    if backup && !reg_keys_to_delete.is_empty() {
-        if let Err(e) = backup_registry_keys(&reg_keys_to_delete).await {
-            errors.push(format!("Failed to create registry log: {}", e));
-        }
-    }
+    let needs_trash = run.entries.iter().any(|e| {
+        e.outcome.needs_restoring() && matches!(e.item, JournalItem::Path { .. })
+    });
+    let bin = if needs_trash {
+        trash::os_limited::list().map_err(|e| format!("Could not read the Recycle Bin: {}", e))
+    } else {
+        Ok(vec![])
+    };
+    let started = chrono::DateTime::parse_from_rfc3339(&run.timestamp).map_or(0, |t| t.timestamp());
 
-    for item in items {
-        match item {
-            FoundItem::File(path) | FoundItem::Directory(path) => {
-                if let Err(e) = trash::delete(&path) {
-                    errors.push(format!("Failed to delete {}: {}", path.display(), e));
+    // Entries still `Pending` belong to a run that stopped part-way. Only what it actually
+    // got to is brought back; the rest was never deleted and is skipped.
+    for entry in run.entries.iter_mut().filter(|e| e.outcome.needs_restoring()) {
+        let pending = entry.outcome == Outcome::Pending;
+        let result = match &entry.item {
+            JournalItem::RegistryKey { path, snapshot } => {
+                if pending && registry.open_key(path).is_ok() {
+                    continue;
                 }
+                regfile::parse(snapshot)
+                    .and_then(|ops| regfile::apply(registry.as_ref(), &ops))
+                    .map_err(|e| format!("Failed to restore registry key {}: {}", path, e))
             }
-            FoundItem::RegistryKey(key_path) => {
-                let (hive_str, sub_path) = match key_path.split_once('\\') {
-                    Some(parts) => parts,
-                    None => {
-                        errors.push(format!("Invalid registry path format: {}", key_path));
-                        continue;
-                    }
-                };
-
-                let hive = match hive_str {
-                    "HKEY_LOCAL_MACHINE" => RegKey::predef(HKEY_LOCAL_MACHINE),
-                    "HKEY_CURRENT_USER" => RegKey::predef(HKEY_CURRENT_USER),
-                    _ => {
-                        errors.push(format!("Unknown registry hive in path: {}", key_path));
-                        continue;
-                    }
-                };
-                
-                if let Some((parent_path, key_to_delete)) = sub_path.rsplit_once('\\') {
-                    if let Ok(parent_key) = hive.open_subkey_with_flags(parent_path, KEY_WRITE) {
-                        if let Err(e) = parent_key.delete_subkey_all(key_to_delete) {
-                            errors.push(format!("Failed to delete registry key {}: {}", key_path, e));
-                        }
-                    } else {
-                        errors.push(format!("Could not open parent key for: {}", key_path));
-                    }
-                } else {
-                    if let Err(e) = hive.delete_subkey_all(sub_path) {
-                        errors.push(format!("Failed to delete registry key {}: {}", key_path, e));
-                    }
+            JournalItem::RegistryValue { key, name, snapshot } => {
+                if pending && registry.get_value(key, name).is_ok() {
+                    continue;
                 }
+                regfile::parse(snapshot)
+                    .and_then(|ops| regfile::apply(registry.as_ref(), &ops))
+                    .map_err(|e| format!("Failed to restore registry value {}\\{}: {}", key, name, e))
             }
-        }
+            JournalItem::Path { original_path, trash_id } => {
+                let found = bin.as_ref().map(|bin| {
+                    bin.iter()
+                        .filter(|t| match trash_id {
+                            Some(id) => t.id.to_string_lossy() == id.as_str(),
+                            None => t.original_path() == *original_path,
+                        })
+                        // The same path may be in the bin from an earlier deletion.
+                        .filter(|t| !pending || t.time_deleted >= started)
+                        .max_by_key(|t| t.time_deleted)
+                        .cloned()
+                });
+                match found {
+                    Ok(Some(item)) => trash::os_limited::restore_all([item])
+                        .map_err(|e| format!("Failed to restore {}: {}", original_path.display(), e)),
+                    Ok(None) if pending => continue,
+                    Ok(None) => Err(format!("{} is no longer in the Recycle Bin", original_path.display())),
+                    Err(e) => Err(e.clone()),
+                }
+            }
+        };
+        entry.outcome = match result {
+            Ok(()) => Outcome::Restored,
+            Err(e) => {
+                errors.push(e.clone());
+                Outcome::RestoreFailed(e)
+            }
+        };
     }
 
+    // Anything that failed can be retried with another undo.
+    run.undone = errors.is_empty();
+    journal.record(&run).map_err(|e| format!("Failed to write cleanup journal: {}", e))?;
+
     if errors.is_empty() {
         Ok(())
     } else {
@@ -526,23 +1461,376 @@ async fn delete_items(items: Vec<FoundItem>, backup: bool) -> Result<(), String>
     }
 }
 
-async fn backup_registry_keys(keys: &[String]) -> Result<(), String> {
-    let backup_dir = dirs::document_dir()
+fn scan_progress_text(progress: &ScanProgress) -> String {
+    format!("{} entries checked, {} matches so far", progress.visited, progress.matches)
+}
+
+// Runs a scan as a subscription, turning its progress reports and result into messages.
+// The subscription is dropped when the scan is cancelled, but the walkers only stop once
+// they see the cancelled `control`.
+fn scan_subscription<F, Fut>(id: u64, control: ScanControl, scan: F) -> Subscription<Message>
+where
+    F: FnOnce(ScanControl) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = Result<Vec<Leftover>, String>> + Send + 'static,
+{
+    subscription::channel(id, 100, move |mut output| async move {
+        let (listener, mut reports) = tokio::sync::mpsc::unbounded_channel();
+        let scan = tokio::spawn(scan(control.reporting_to(listener)));
+        // The channel closes once the scan has finished and dropped its listener.
+        while let Some(event) = reports.recv().await {
+            let message = match event {
+                ScanEvent::Progress(progress) => Message::ScanProgressed(progress),
+                ScanEvent::Found(found) => Message::LeftoversFound(found),
+            };
+            let _ = output.send(message).await;
+        }
+        let result = scan.await.unwrap_or_else(|e| Err(e.to_string()));
+        let _ = output.send(Message::ScanCompleted(result)).await;
+        iced::futures::future::pending().await
+    })
+}
+
+// Installers write all over the known folders, Program Files and ProgramData included.
+fn trace_roots(folders: &dyn KnownFolders) -> Vec<PathBuf> {
+    folders.existing()
+}
+
+async fn start_trace(roots: Vec<PathBuf>, manifests: ManifestStore, registry: Arc<dyn RegistryBackend>) -> Result<(), String> {
+    let snapshot = Snapshot::take(&roots, &trace::TRACED_REGISTRY_ROOTS, registry.as_ref());
+    manifests.save_snapshot(&snapshot)
+}
+
+// Diffs against the pending snapshot and files the changes under every program the
+// install registered (bundles can register several).
+async fn finish_trace(
+    roots: Vec<PathBuf>,
+    manifests: ManifestStore,
+    registry: Arc<dyn RegistryBackend>,
+) -> Result<Vec<InstallManifest>, String> {
+    let before = manifests.load_snapshot()?.ok_or("No install trace is in progress")?;
+    let after = Snapshot::take(&roots, &trace::TRACED_REGISTRY_ROOTS, registry.as_ref());
+    let changes = InstallManifest::diff(&before, &after);
+
+    let installed: Vec<ProgramInfo> = load_installed_programs(registry)
+        .await?
+        .into_iter()
+        .filter(|p| changes.registry_keys.iter().any(|key| key.eq_ignore_ascii_case(&p.registry_key)))
+        .collect();
+    if installed.is_empty() {
+        return Err("No newly installed program was registered since the trace started. Finish the install first, or cancel the trace.".to_string());
+    }
+
+    let mut recorded = Vec::new();
+    for program in installed {
+        let manifest = InstallManifest { program: program.name, registry_key: program.registry_key, ..changes.clone() };
+        manifests.save(&manifest)?;
+        recorded.push(manifest);
+    }
+    manifests.discard_snapshot()?;
+    Ok(recorded)
+}
+
+async fn create_plan(
+    program: String,
+    items: Vec<FoundItem>,
+    registry: Arc<dyn RegistryBackend>,
+) -> Result<(DeletionPlan, PathBuf), String> {
+    let mounted = MountedHives::for_keys(registry.clone(), &registry_keys(&items));
+    let plan = DeletionPlan::build(&program, &items, registry.as_ref());
+    drop(mounted);
+
+    let plan_dir = backup_dir()?;
+    fs::create_dir_all(&plan_dir).map_err(|e| e.to_string())?;
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let plan_path = plan_dir.join(format!("plan-{}.json", timestamp));
+    plan.save(&plan_path)?;
+
+    Ok((plan, plan_path))
+}
+
+// Runs a previously reviewed plan, refusing to touch anything if the system has changed since.
+async fn execute_plan(
+    plan: DeletionPlan,
+    backup: bool,
+    registry: Arc<dyn RegistryBackend>,
+    journal: Journal,
+    protected: ProtectedItems,
+) -> Result<(), DeleteError> {
+    let mounted = MountedHives::for_keys(registry.clone(), &registry_keys(&plan.items()));
+    let drift = plan.drift(registry.as_ref());
+    drop(mounted);
+    if !drift.is_empty() {
+        return Err(DeleteError::NothingDeleted(format!(
+            "The plan no longer matches this system, so nothing was deleted:\n{}",
+            drift.join("\n")
+        )));
+    }
+    delete_items(plan.program.clone(), plan.items(), backup, registry, journal, protected).await
+}
+
+fn backup_dir() -> Result<PathBuf, String> {
+    Ok(dirs::document_dir()
         .ok_or("Could not find Documents directory")?
-        .join("KuriUninstaller_Backups");
+        .join("KuriUninstaller_Backups"))
+}
+
+async fn backup_registry_keys(
+    registry: &dyn RegistryBackend,
+    keys: &[String],
+    values: &[(String, String)],
+) -> Result<PathBuf, String> {
+    let backup_dir = backup_dir()?;
     fs::create_dir_all(&backup_dir).map_err(|e| e.to_string())?;
 
+    // Keys and values that vanished since the scan have nothing left to back up.
+    let existing: Vec<String> = keys.iter().filter(|k| registry.open_key(k).is_ok()).cloned().collect();
+    let existing_values: Vec<(String, String)> =
+        values.iter().filter(|(key, name)| registry.get_value(key, name).is_ok()).cloned().collect();
+    let contents = regfile::export(registry, &existing, &existing_values).map_err(|e| e.to_string())?;
+
     let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
-    let backup_file_path = backup_dir.join(format!("deleted_keys_log-{}.txt", timestamp));
-    
-    let mut file = fs::File::create(backup_file_path).map_err(|e| e.to_string())?;
-    
-    writeln!(file, "Log of registry keys deleted by Kuri Uninstaller at {}", timestamp).map_err(|e| e.to_string())?;
-    writeln!(file, "--------------------------------------------------").map_err(|e| e.to_string())?;
-    
-    for key in keys {
-        writeln!(file, "{}", key).map_err(|e| e.to_string())?;
-    }
-    
-    Ok(())
-}
\ No newline at end of file
+    let backup_file_path = backup_dir.join(format!("deleted_keys-{}.reg", timestamp));
+    fs::write(&backup_file_path, regfile::encode_utf16le(&contents)).map_err(|e| e.to_string())?;
+
+    Ok(backup_file_path)
+}
+
+async fn list_backups() -> Result<Vec<PathBuf>, String> {
+    let backup_dir = backup_dir()?;
+    if !backup_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups: Vec<PathBuf> = fs::read_dir(&backup_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("reg")))
+        .collect();
+
+    // File names carry a timestamp, so reverse order puts the newest first.
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
+}
+
+async fn restore_backup(path: PathBuf, registry: Arc<dyn RegistryBackend>) -> Result<(), String> {
+    let bytes = fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let text = regfile::decode(&bytes)?;
+    let ops = regfile::parse(&text)?;
+    let keys: Vec<String> = ops
+        .iter()
+        .map(|op| match op {
+            RegOperation::CreateKey(key) | RegOperation::DeleteKey(key) => key.clone(),
+            RegOperation::SetValue { key, .. } | RegOperation::DeleteValue { key, .. } => key.clone(),
+        })
+        .collect();
+    let _mounted = MountedHives::for_keys(registry.clone(), &keys);
+    regfile::apply(registry.as_ref(), &ops)
+}
+
+fn registry_keys(items: &[FoundItem]) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            FoundItem::RegistryKey(key) | FoundItem::RegistryValue { key, .. } => Some(key.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registry::{MemoryRegistry, RegValue};
+    use known_folders::EnvKnownFolders;
+
+    const UNINSTALL: &str = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall";
+    const UNINSTALL_WOW: &str = r"HKEY_LOCAL_MACHINE\SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall";
+
+    fn add_program(reg: &MemoryRegistry, base: &str, key: &str, name: &str, version: &str) {
+        let path = registry::join(base, key);
+        reg.set_value(&path, "DisplayName", &RegValue::String(name.to_string())).unwrap();
+        reg.set_value(&path, "DisplayVersion", &RegValue::String(version.to_string())).unwrap();
+    }
+
+    #[tokio::test]
+    async fn loads_sorted_unique_programs_from_both_views() {
+        let reg = MemoryRegistry::new();
+        add_program(&reg, UNINSTALL, "zeta", "Zeta Tool", "2.0");
+        add_program(&reg, UNINSTALL, "{GUID-1}", "alpha app", "1.0");
+        add_program(&reg, UNINSTALL_WOW, "alpha-32", "alpha app", "1.0");
+        add_program(&reg, UNINSTALL_WOW, "empty", "", "");
+        reg.set_value(&registry::join(UNINSTALL, "zeta"), "InstallLocation", &RegValue::String(r"C:\Zeta".into())).unwrap();
+
+        let programs = load_installed_programs(Arc::new(reg)).await.unwrap();
+
+        let names: Vec<_> = programs.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["alpha app", "Zeta Tool"]);
+        assert_eq!(programs[1].version, "2.0");
+        assert_eq!(programs[1].install_location, Some(PathBuf::from(r"C:\Zeta")));
+        assert_eq!(programs[0].install_location, None);
+        assert_eq!(programs[0].registry_key, registry::join(UNINSTALL, "{GUID-1}"));
+    }
+
+    #[tokio::test]
+    async fn reads_publisher_and_uninstall_metadata() {
+        let reg = MemoryRegistry::new();
+        add_program(&reg, UNINSTALL, "{ABC}", "Contoso Studio", "3.1");
+        let key = registry::join(UNINSTALL, "{ABC}");
+        reg.set_value(&key, "Publisher", &RegValue::String("Contoso Ltd. ".into())).unwrap();
+        reg.set_value(&key, "DisplayIcon", &RegValue::String(r"C:\Contoso\studio.exe,0".into())).unwrap();
+        reg.set_value(&key, "UninstallString", &RegValue::ExpandString(r#""C:\Contoso\unins000.exe""#.into())).unwrap();
+        reg.set_value(&key, "QuietUninstallString", &RegValue::ExpandString(r#""C:\Contoso\unins000.exe" /SILENT"#.into())).unwrap();
+        reg.set_value(&key, "InstallSource", &RegValue::String("".into())).unwrap();
+
+        let programs = load_installed_programs(Arc::new(reg)).await.unwrap();
+
+        assert_eq!(programs[0].publisher.as_deref(), Some("Contoso Ltd."));
+        assert_eq!(programs[0].display_icon.as_deref(), Some(r"C:\Contoso\studio.exe,0"));
+        assert_eq!(programs[0].uninstall_string.as_deref(), Some(r#""C:\Contoso\unins000.exe""#));
+        assert_eq!(programs[0].quiet_uninstall_string.as_deref(), Some(r#""C:\Contoso\unins000.exe" /SILENT"#));
+        assert_eq!(programs[0].install_source, None);
+    }
+
+    #[tokio::test]
+    async fn trace_records_install_under_the_new_program() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(&root).unwrap();
+        let manifests = ManifestStore::at(dir.path().join("manifests"));
+        let reg = Arc::new(MemoryRegistry::new());
+        add_program(&reg, UNINSTALL, "Existing", "Existing App", "1.0");
+        let folders: Arc<dyn KnownFolders> = Arc::new(EnvKnownFolders::from_vars([("ProgramData", root.to_string_lossy())]));
+        assert_eq!(trace_roots(folders.as_ref()), std::slice::from_ref(&root));
+
+        start_trace(trace_roots(folders.as_ref()), manifests.clone(), reg.clone()).await.unwrap();
+        assert!(finish_trace(vec![root.clone()], manifests.clone(), reg.clone()).await.is_err());
+
+        fs::create_dir_all(root.join("Oddly Named").join("bin")).unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Vendor\Settings").unwrap();
+        add_program(&reg, UNINSTALL, "{NEW}", "New App", "2.0");
+        let recorded = finish_trace(vec![root.clone()], manifests.clone(), reg.clone()).await.unwrap();
+
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].program, "New App");
+        assert_eq!(recorded[0].directories, [root.join("Oddly Named")]);
+        assert!(!manifests.has_pending_snapshot());
+
+        let programs = load_installed_programs(reg.clone()).await.unwrap();
+        let new_app = programs.into_iter().find(|p| p.name == "New App").unwrap();
+        let leftovers = scan_for_leftovers(new_app, reg.clone(), manifests, folders, Arc::default(), ScanOptions::default(), ScanControl::default()).await.unwrap();
+        let items: Vec<FoundItem> = leftovers.into_iter().map(|l| l.item).collect();
+        assert_eq!(
+            items,
+            [
+                FoundItem::Directory(root.join("Oddly Named")),
+                FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Vendor".into()),
+                FoundItem::RegistryKey(registry::join(UNINSTALL, "{NEW}")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_removes_whole_registry_subtree() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("journal.json"));
+        let reg = Arc::new(MemoryRegistry::new());
+        reg.set_value(r"HKEY_CURRENT_USER\Software\Contoso\Sub", "Value", &RegValue::Dword(1)).unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Keep").unwrap();
+
+        let items = vec![FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into())];
+        delete_items("Contoso".into(), items, false, reg.clone(), journal.clone(), ProtectedItems::default()).await.unwrap();
+
+        assert_eq!(reg.enum_keys(r"HKEY_CURRENT_USER\Software").unwrap(), ["Keep"]);
+
+        let missing = vec![FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into())];
+        let error = delete_items("Contoso".into(), missing, false, reg, journal, ProtectedItems::default()).await.unwrap_err();
+        assert!(matches!(error, DeleteError::Failed { failed: 1, .. }));
+    }
+
+    #[tokio::test]
+    async fn delete_refuses_protected_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("journal.json"));
+        let reg = Arc::new(MemoryRegistry::new());
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso").unwrap();
+        let mut protected = ProtectedItems::default();
+        protected.protect_key(r"HKEY_CURRENT_USER\Software", protected::Protection::Itself);
+
+        let items = vec![
+            FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into()),
+            FoundItem::RegistryKey(r"HKEY_USERS\S-1-5-21-1-1001\Software".into()),
+        ];
+        let error = delete_items("Contoso".into(), items, false, reg.clone(), journal.clone(), protected).await.unwrap_err();
+
+        assert!(matches!(&error, DeleteError::NothingDeleted(e) if e.contains(r"HKEY_USERS\S-1-5-21-1-1001\Software")));
+        assert!(reg.open_key(r"HKEY_CURRENT_USER\Software\Contoso").is_ok());
+        assert!(journal.load().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undo_restores_deleted_registry_keys_and_values_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("journal.json"));
+        let reg = Arc::new(MemoryRegistry::new());
+        let key = r"HKEY_CURRENT_USER\Software\Contoso";
+        reg.set_value(&registry::join(key, "Sub"), "Value", &RegValue::Dword(7)).unwrap();
+        reg.set_value(key, "Name", &RegValue::String("x".into())).unwrap();
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        reg.set_value(run, "Contoso", &RegValue::String(r"C:\Contoso\tray.exe".into())).unwrap();
+        reg.set_value(run, "Fabrikam", &RegValue::String(r"C:\Fabrikam\agent.exe".into())).unwrap();
+        let before = regfile::export_keys(reg.as_ref(), &[key.to_string()]).unwrap();
+
+        let items = vec![
+            FoundItem::RegistryKey(key.into()),
+            FoundItem::RegistryValue { key: run.into(), name: "Contoso".into() },
+        ];
+        delete_items("Contoso".into(), items, false, reg.clone(), journal.clone(), ProtectedItems::default()).await.unwrap();
+        assert!(reg.open_key(key).is_err());
+        assert_eq!(reg.enum_values(run).unwrap().len(), 1);
+
+        let runs = journal.load().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].program, "Contoso");
+        assert_eq!(runs[0].entries[0].outcome, Outcome::Deleted);
+
+        undo_last_cleanup(journal.clone(), reg.clone()).await.unwrap();
+        assert_eq!(regfile::export_keys(reg.as_ref(), &[key.to_string()]).unwrap(), before);
+        assert_eq!(reg.get_string(run, "Contoso").as_deref(), Some(r"C:\Contoso\tray.exe"));
+        assert!(journal.load().unwrap()[0].entries.iter().all(|e| e.outcome == Outcome::Restored));
+
+        assert!(undo_last_cleanup(journal, reg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn undo_restores_what_an_interrupted_run_deleted_and_retries_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::at(dir.path().join("journal.json"));
+        let reg = Arc::new(MemoryRegistry::new());
+        let (gone, kept) = (r"HKEY_CURRENT_USER\Software\Contoso", r"HKEY_CURRENT_USER\Software\Contoso Cache");
+        reg.set_value(gone, "Name", &RegValue::String("x".into())).unwrap();
+        reg.set_value(kept, "Size", &RegValue::Dword(1)).unwrap();
+
+        // Stopped after deleting the first key, before the journal said so.
+        let mut run = CleanupRun::new("Contoso");
+        for key in [gone, kept] {
+            let snapshot = regfile::export_keys(reg.as_ref(), &[key.to_string()]).unwrap();
+            run.push(JournalItem::RegistryKey { path: key.into(), snapshot }, Outcome::Pending);
+        }
+        let broken = JournalItem::RegistryKey { path: r"HKEY_CURRENT_USER\Software\Broken".into(), snapshot: "garbage".into() };
+        run.push(broken, Outcome::Deleted);
+        journal.record(&run).unwrap();
+        reg.delete_subtree(gone).unwrap();
+        reg.set_value(kept, "Size", &RegValue::Dword(2)).unwrap();
+
+        assert!(undo_last_cleanup(journal.clone(), reg.clone()).await.is_err());
+        assert_eq!(reg.get_string(gone, "Name").as_deref(), Some("x"));
+        assert_eq!(reg.get_value(kept, "Size").unwrap(), RegValue::Dword(2));
+
+        let run = journal.last_undoable().unwrap().expect("a failed restore can be retried");
+        assert_eq!(run.entries[0].outcome, Outcome::Restored);
+        assert_eq!(run.entries[1].outcome, Outcome::Pending);
+        assert!(matches!(run.entries[2].outcome, Outcome::RestoreFailed(_)));
+    }
+}
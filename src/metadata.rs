@@ -0,0 +1,279 @@
+use crate::plan;
+use crate::registry::{RegValue, RegistryBackend};
+use crate::scan;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+// Anything touched more recently than this is flagged as possibly still in use.
+pub const RECENT_DAYS: i64 = 7;
+// A key's preview shows at most this many values.
+const PREVIEW_VALUES: usize = 20;
+// Longer value data is cut off in the preview.
+const PREVIEW_CHARS: usize = 80;
+
+// What's on disk for a found file or folder. For a folder, sizes and times cover everything
+// inside it: a folder whose newest file was written yesterday is probably still in use.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ItemMetadata {
+    pub bytes: u64,
+    // Files inside a folder; `None` for a single file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<u64>,
+    pub modified: Option<DateTime<Local>>,
+    pub accessed: Option<DateTime<Local>>,
+    pub owner: Option<String>,
+}
+
+impl ItemMetadata {
+    pub fn read(path: &Path) -> Option<Self> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        let mut item = ItemMetadata {
+            bytes: 0,
+            files: None,
+            modified: meta.modified().ok().map(DateTime::from),
+            accessed: meta.accessed().ok().map(DateTime::from),
+            owner: owner(path),
+        };
+        if !meta.is_dir() {
+            item.bytes = meta.len();
+            return Some(item);
+        }
+
+        let mut files = 0;
+        let entries = walkdir::WalkDir::new(path)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|e| !scan::is_link(e))
+            .filter_map(|e| e.ok());
+        for entry in entries {
+            let Ok(meta) = entry.metadata() else { continue };
+            if meta.is_file() {
+                files += 1;
+                item.bytes += meta.len();
+            }
+            item.modified = item.modified.max(meta.modified().ok().map(DateTime::from));
+            item.accessed = item.accessed.max(meta.accessed().ok().map(DateTime::from));
+        }
+        item.files = Some(files);
+        Some(item)
+    }
+
+    pub fn recently_modified(&self, now: DateTime<Local>) -> bool {
+        self.modified.is_some_and(|modified| (now - modified).num_days() < RECENT_DAYS)
+    }
+
+    pub fn size(&self) -> String {
+        match self.files {
+            Some(files) => format!("{} files, {}", files, plan::format_size(self.bytes)),
+            None => plan::format_size(self.bytes),
+        }
+    }
+
+    // e.g. "12 files, 3.4 MB, modified yesterday, owner CONTOSO\alice"
+    pub fn summary(&self, now: DateTime<Local>) -> String {
+        let mut parts = vec![self.size()];
+        if let Some(modified) = self.modified {
+            parts.push(format!("modified {}", age(modified, now)));
+        }
+        if let Some(accessed) = self.accessed {
+            parts.push(format!("accessed {}", age(accessed, now)));
+        }
+        if let Some(owner) = &self.owner {
+            parts.push(format!("owner {}", owner));
+        }
+        parts.join(", ")
+    }
+}
+
+// What a found registry key holds. The counts cover the whole subtree, which is what
+// tells an empty stub key apart from one holding another product's configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyMetadata {
+    pub subkeys: usize,
+    pub values: usize,
+    pub last_write: Option<DateTime<Local>>,
+    // The key's own values as `(name, data)`, for the expandable preview.
+    pub preview: Vec<(String, String)>,
+}
+
+impl KeyMetadata {
+    pub fn read(registry: &dyn RegistryBackend, path: &str) -> Option<Self> {
+        let (subkeys, values) = plan::count_subtree(registry, path).ok()?;
+        let preview = registry
+            .enum_values(path)
+            .unwrap_or_default()
+            .into_iter()
+            .take(PREVIEW_VALUES)
+            .map(|(name, value)| (if name.is_empty() { "(Default)".to_string() } else { name }, format_value(&value)))
+            .collect();
+        Some(KeyMetadata {
+            subkeys,
+            values,
+            last_write: registry.last_write_time(path).ok().map(DateTime::from),
+            preview,
+        })
+    }
+
+    pub fn size(&self) -> String {
+        format!("{} subkeys, {} values", self.subkeys, self.values)
+    }
+
+    // e.g. "2 subkeys, 14 values, last written 3 days ago"
+    pub fn summary(&self, now: DateTime<Local>) -> String {
+        match self.last_write {
+            Some(time) => format!("{}, last written {}", self.size(), age(time, now)),
+            None => self.size(),
+        }
+    }
+}
+
+pub fn format_value(value: &RegValue) -> String {
+    let text = match value {
+        RegValue::String(s) | RegValue::ExpandString(s) => format!("\"{}\"", s),
+        RegValue::MultiString(list) => list.iter().map(|s| format!("\"{}\"", s)).collect::<Vec<_>>().join(", "),
+        RegValue::Dword(d) => format!("0x{:08x} ({})", d, d),
+        RegValue::Qword(q) => format!("0x{:016x} ({})", q, q),
+        RegValue::Binary(bytes) => bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "),
+    };
+    if text.chars().count() > PREVIEW_CHARS {
+        format!("{}…", text.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        text
+    }
+}
+
+pub fn age(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    match (now.date_naive() - time.date_naive()).num_days() {
+        ..=0 => "today".to_string(),
+        1 => "yesterday".to_string(),
+        days @ 2..=30 => format!("{} days ago", days),
+        _ => time.format("%Y-%m-%d").to_string(),
+    }
+}
+
+// --- Owners ---
+
+#[cfg(unix)]
+fn owner(path: &Path) -> Option<String> {
+    use std::ffi::CStr;
+    use std::os::unix::fs::MetadataExt;
+
+    let uid = fs::symlink_metadata(path).ok()?.uid();
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    let mut found = std::ptr::null_mut();
+    let result = unsafe { libc::getpwuid_r(uid, &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut found) };
+    if result != 0 || found.is_null() {
+        return Some(uid.to_string());
+    }
+    Some(unsafe { CStr::from_ptr(passwd.pw_name) }.to_string_lossy().into_owned())
+}
+
+#[cfg(windows)]
+fn owner(path: &Path) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{LocalFree, ERROR_SUCCESS};
+    use windows_sys::Win32::Security::Authorization::{GetNamedSecurityInfoW, SE_FILE_OBJECT};
+    use windows_sys::Win32::Security::{LookupAccountSidW, OWNER_SECURITY_INFORMATION};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut sid = std::ptr::null_mut();
+    let mut descriptor = std::ptr::null_mut();
+    let status = unsafe {
+        GetNamedSecurityInfoW(
+            wide.as_ptr(),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            &mut sid,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut descriptor,
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+
+    // `sid` points into `descriptor`, which has to outlive the lookup.
+    let (mut name, mut domain) = ([0u16; 256], [0u16; 256]);
+    let (mut name_len, mut domain_len) = (name.len() as u32, domain.len() as u32);
+    let mut kind = 0;
+    let found = unsafe {
+        LookupAccountSidW(
+            std::ptr::null(),
+            sid,
+            name.as_mut_ptr(),
+            &mut name_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut kind,
+        )
+    };
+    unsafe { LocalFree(descriptor) };
+    if found == 0 {
+        return None;
+    }
+    let name = String::from_utf16_lossy(&name[..name_len as usize]);
+    let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+    Some(if domain.is_empty() { name } else { format!("{}\\{}", domain, name) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn folders_add_up_their_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Cache")).unwrap();
+        fs::write(dir.path().join("settings.ini"), "12345").unwrap();
+        fs::write(dir.path().join("Cache").join("a.bin"), "123").unwrap();
+
+        let folder = ItemMetadata::read(dir.path()).unwrap();
+        assert_eq!((folder.files, folder.bytes), (Some(2), 8));
+        assert!(folder.recently_modified(Local::now()));
+        assert!(folder.owner.is_some());
+
+        let file = ItemMetadata::read(&dir.path().join("settings.ini")).unwrap();
+        assert_eq!((file.files, file.bytes), (None, 5));
+        assert_eq!(ItemMetadata::read(&dir.path().join("missing")), None);
+    }
+
+    #[test]
+    fn reads_key_counts_and_preview() {
+        use crate::registry::MemoryRegistry;
+
+        let reg = MemoryRegistry::new();
+        let key = r"HKEY_CURRENT_USER\Software\Contoso";
+        reg.set_value(key, "", &RegValue::String("Contoso".into())).unwrap();
+        reg.set_value(key, "Flags", &RegValue::Dword(10)).unwrap();
+        reg.set_value(&format!("{}\\Settings", key), "Theme", &RegValue::Binary(vec![1, 0xab])).unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Stub").unwrap();
+
+        let details = KeyMetadata::read(&reg, key).unwrap();
+        assert_eq!((details.subkeys, details.values), (1, 3));
+        assert!(details.last_write.is_some());
+        assert_eq!(
+            details.preview,
+            [("(Default)".to_string(), "\"Contoso\"".to_string()), ("Flags".to_string(), "0x0000000a (10)".to_string())]
+        );
+        assert_eq!(format_value(&RegValue::Binary(vec![1, 0xab])), "01 ab");
+
+        let stub = KeyMetadata::read(&reg, r"HKEY_CURRENT_USER\Software\Stub").unwrap();
+        assert_eq!(stub.size(), "0 subkeys, 0 values");
+        assert_eq!(KeyMetadata::read(&reg, r"HKEY_CURRENT_USER\Software\Missing"), None);
+    }
+
+    #[test]
+    fn describes_ages() {
+        let now = Local.with_ymd_and_hms(2024, 5, 20, 9, 0, 0).unwrap();
+        assert_eq!(age(Local.with_ymd_and_hms(2024, 5, 20, 1, 0, 0).unwrap(), now), "today");
+        assert_eq!(age(Local.with_ymd_and_hms(2024, 5, 19, 23, 0, 0).unwrap(), now), "yesterday");
+        assert_eq!(age(Local.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap(), now), "10 days ago");
+        assert_eq!(age(Local.with_ymd_and_hms(2023, 1, 2, 12, 0, 0).unwrap(), now), "2023-01-02");
+    }
+}
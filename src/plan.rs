@@ -0,0 +1,262 @@
+use crate::metadata;
+use crate::registry::{self, RegistryBackend};
+use crate::scan;
+use crate::FoundItem;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// --- Deletion Plan ---
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeletionPlan {
+    pub program: String,
+    pub created: String,
+    pub operations: Vec<PlannedOperation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedOperation {
+    TrashFile { path: PathBuf, bytes: u64 },
+    TrashDirectory { path: PathBuf, files: u64, bytes: u64 },
+    DeleteRegistryKey { path: String, subkeys: usize, values: usize },
+    DeleteRegistryValue { key: String, name: String, data: String },
+    // The item disappeared between the scan and the plan; kept so the review shows it.
+    Missing { item: String },
+}
+
+impl DeletionPlan {
+    // Resolves each item against the live filesystem and registry without changing anything.
+    pub fn build(program: &str, items: &[FoundItem], registry: &dyn RegistryBackend) -> Self {
+        let operations = items.iter().map(|item| resolve(item, registry)).collect();
+        DeletionPlan {
+            program: program.to_string(),
+            created: chrono::Local::now().to_rfc3339(),
+            operations,
+        }
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.operations
+            .iter()
+            .map(|op| match op {
+                PlannedOperation::TrashFile { bytes, .. } | PlannedOperation::TrashDirectory { bytes, .. } => *bytes,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    pub fn items(&self) -> Vec<FoundItem> {
+        self.operations
+            .iter()
+            .filter_map(|op| match op {
+                PlannedOperation::TrashFile { path, .. } => Some(FoundItem::File(path.clone())),
+                PlannedOperation::TrashDirectory { path, .. } => Some(FoundItem::Directory(path.clone())),
+                PlannedOperation::DeleteRegistryKey { path, .. } => Some(FoundItem::RegistryKey(path.clone())),
+                PlannedOperation::DeleteRegistryValue { key, name, .. } => {
+                    Some(FoundItem::RegistryValue { key: key.clone(), name: name.clone() })
+                }
+                PlannedOperation::Missing { .. } => None,
+            })
+            .collect()
+    }
+
+    // Lists every operation whose target no longer looks the way it did when the plan was made.
+    pub fn drift(&self, registry: &dyn RegistryBackend) -> Vec<String> {
+        let current = DeletionPlan::build(&self.program, &self.items(), registry);
+        self.operations
+            .iter()
+            .filter(|op| !matches!(op, PlannedOperation::Missing { .. }))
+            .zip(&current.operations)
+            .filter(|(planned, now)| planned != now)
+            .map(|(planned, now)| format!("{} (now: {})", describe(planned), describe(now)))
+            .collect()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.operations.iter().map(describe).collect();
+        lines.push(format!(
+            "Total: {} operations, {} reclaimed",
+            self.operations.len(),
+            format_size(self.total_bytes())
+        ));
+        lines
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Deletion plan for {} (created {})\n", self.program, self.created);
+        for line in self.lines() {
+            text.push_str(&format!("  {}\n", line));
+        }
+        text
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid plan file {}: {}", path.display(), e))
+    }
+}
+
+fn resolve(item: &FoundItem, registry: &dyn RegistryBackend) -> PlannedOperation {
+    let missing = || PlannedOperation::Missing { item: item.to_string() };
+    match item {
+        FoundItem::File(path) | FoundItem::Directory(path) => match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => {
+                let (files, bytes) = directory_size(path);
+                PlannedOperation::TrashDirectory { path: path.clone(), files, bytes }
+            }
+            Ok(meta) => PlannedOperation::TrashFile { path: path.clone(), bytes: meta.len() },
+            Err(_) => missing(),
+        },
+        FoundItem::RegistryKey(key) => match count_subtree(registry, key) {
+            Ok((subkeys, values)) => PlannedOperation::DeleteRegistryKey { path: key.clone(), subkeys, values },
+            Err(_) => missing(),
+        },
+        FoundItem::RegistryValue { key, name } => match registry.get_value(key, name) {
+            Ok(value) => PlannedOperation::DeleteRegistryValue {
+                key: key.clone(),
+                name: name.clone(),
+                data: metadata::format_value(&value),
+            },
+            Err(_) => missing(),
+        },
+    }
+}
+
+fn describe(op: &PlannedOperation) -> String {
+    match op {
+        PlannedOperation::TrashFile { path, bytes } => {
+            format!("Trash file    {} ({})", path.display(), format_size(*bytes))
+        }
+        PlannedOperation::TrashDirectory { path, files, bytes } => {
+            format!("Trash folder  {} ({} files, {})", path.display(), files, format_size(*bytes))
+        }
+        PlannedOperation::DeleteRegistryKey { path, subkeys, values } => {
+            format!("Delete key    {} ({} subkeys, {} values)", path, subkeys, values)
+        }
+        PlannedOperation::DeleteRegistryValue { key, name, data } => {
+            let name = if name.is_empty() { "(Default)" } else { name };
+            format!("Delete value  {}\\{} = {}", key, name, data)
+        }
+        PlannedOperation::Missing { item } => format!("Skip missing  {}", item),
+    }
+}
+
+fn directory_size(path: &Path) -> (u64, u64) {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !scan::is_link(e))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .fold((0, 0), |(files, bytes), e| {
+            (files + 1, bytes + e.metadata().map(|m| m.len()).unwrap_or(0))
+        })
+}
+
+// Counts every subkey and value below (and including the values of) `path`.
+pub fn count_subtree(registry: &dyn RegistryBackend, path: &str) -> io::Result<(usize, usize)> {
+    let mut values = registry.enum_values(path)?.len();
+    let mut subkeys = 0;
+    for child in registry.enum_keys(path)? {
+        let (child_keys, child_values) = count_subtree(registry, &registry::join(path, &child))?;
+        subkeys += 1 + child_keys;
+        values += child_values;
+    }
+    Ok((subkeys, values))
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    #[test]
+    fn resolves_items_without_touching_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("Contoso");
+        fs::create_dir_all(folder.join("cache")).unwrap();
+        fs::write(folder.join("a.log"), [0u8; 10]).unwrap();
+        fs::write(folder.join("cache").join("b.bin"), [0u8; 20]).unwrap();
+        let file = dir.path().join("contoso.ini");
+        fs::write(&file, [0u8; 5]).unwrap();
+
+        let reg = MemoryRegistry::new();
+        let key = r"HKEY_CURRENT_USER\Software\Contoso";
+        reg.set_value(key, "A", &RegValue::Dword(1)).unwrap();
+        reg.set_value(&registry::join(key, r"Sub\Deeper"), "B", &RegValue::Dword(2)).unwrap();
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        reg.set_value(run, "Contoso", &RegValue::String("tray.exe".into())).unwrap();
+
+        let items = vec![
+            FoundItem::Directory(folder.clone()),
+            FoundItem::File(file.clone()),
+            FoundItem::RegistryKey(key.into()),
+            FoundItem::RegistryValue { key: run.into(), name: "Contoso".into() },
+            FoundItem::File(dir.path().join("gone.txt")),
+        ];
+        let plan = DeletionPlan::build("Contoso", &items, &reg);
+
+        assert_eq!(
+            plan.operations[..4],
+            [
+                PlannedOperation::TrashDirectory { path: folder.clone(), files: 2, bytes: 30 },
+                PlannedOperation::TrashFile { path: file.clone(), bytes: 5 },
+                PlannedOperation::DeleteRegistryKey { path: key.into(), subkeys: 2, values: 2 },
+                PlannedOperation::DeleteRegistryValue { key: run.into(), name: "Contoso".into(), data: "\"tray.exe\"".into() },
+            ]
+        );
+        assert!(matches!(plan.operations[4], PlannedOperation::Missing { .. }));
+        assert_eq!(plan.total_bytes(), 35);
+        assert_eq!(plan.items(), items[..4]);
+        assert!(folder.exists() && file.exists());
+        assert!(reg.open_key(key).is_ok());
+    }
+
+    #[test]
+    fn saved_plan_reloads_and_detects_drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("contoso.ini");
+        fs::write(&file, "abc").unwrap();
+        let reg = MemoryRegistry::new();
+
+        let plan = DeletionPlan::build("Contoso", &[FoundItem::File(file.clone())], &reg);
+        let plan_path = dir.path().join("plan.json");
+        plan.save(&plan_path).unwrap();
+
+        let loaded = DeletionPlan::load(&plan_path).unwrap();
+        assert_eq!(loaded, plan);
+        assert!(loaded.drift(&reg).is_empty());
+
+        fs::write(&file, "abcdef").unwrap();
+        assert_eq!(loaded.drift(&reg).len(), 1);
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+}
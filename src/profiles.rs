@@ -0,0 +1,282 @@
+use crate::registry::{self, RegistryBackend};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const PROFILE_LIST: &str = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\ProfileList";
+// Signed-out users' hives are mounted under this prefix so their keys are recognisable later.
+const MOUNT_PREFIX: &str = "KuriUninstaller-";
+// Folders in the profiles root that are templates or shared rather than someone's profile.
+const NON_PROFILE_DIRS: [&str; 4] = ["Public", "Default", "Default User", "All Users"];
+// Per-user application data inside a profile.
+const APP_DATA_DIRS: [[&str; 2]; 3] = [["AppData", "Local"], ["AppData", "Roaming"], ["AppData", "LocalLow"]];
+
+// --- User Profiles ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserProfile {
+    pub sid: String,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+// Local and Microsoft Entra accounts; the service accounts (S-1-5-18/19/20) have nothing
+// of interest, and `<SID>_Classes` is only a view of the user's Software\Classes.
+fn is_user_sid(sid: &str) -> bool {
+    (sid.starts_with("S-1-5-21-") || sid.starts_with("S-1-12-1-")) && !sid.ends_with("_Classes")
+}
+
+pub fn list_profiles(registry: &dyn RegistryBackend) -> Vec<UserProfile> {
+    let sids = registry.enum_keys(PROFILE_LIST).unwrap_or_default();
+    sids.into_iter()
+        .filter(|sid| is_user_sid(sid))
+        .filter_map(|sid| {
+            let path = registry.get_string(&registry::join(PROFILE_LIST, &sid), "ProfileImagePath")?;
+            let path = expand_env(&path);
+            let name = path.trim_end_matches(['\\', '/']).rsplit(['\\', '/']).next().unwrap_or(&path).to_string();
+            Some(UserProfile { sid, name, path: PathBuf::from(path) })
+        })
+        .collect()
+}
+
+// Every local profile. `ProfileList` is authoritative on Windows; the folders under
+// `profiles_root` (by default the parent of our own home folder) are used when it is
+// empty, and merged in when a root is given explicitly.
+pub fn user_profiles(registry: &dyn RegistryBackend, profiles_root: Option<&Path>) -> Vec<UserProfile> {
+    let mut profiles = list_profiles(registry);
+    let root = match profiles_root {
+        Some(root) => Some(root.to_path_buf()),
+        None if profiles.is_empty() => dirs::home_dir().and_then(|home| home.parent().map(Path::to_path_buf)),
+        None => None,
+    };
+    let Some(entries) = root.and_then(|root| std::fs::read_dir(root).ok()) else { return profiles };
+
+    let mut found: Vec<UserProfile> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| UserProfile { sid: String::new(), name: e.file_name().to_string_lossy().into_owned(), path: e.path() })
+        .filter(|p| !NON_PROFILE_DIRS.iter().any(|skip| skip.eq_ignore_ascii_case(&p.name)))
+        .filter(|p| !profiles.iter().any(|known| known.path == p.path))
+        .collect();
+    found.sort_by_key(|p| p.name.to_lowercase());
+    profiles.extend(found);
+    profiles
+}
+
+// AppData\Local, AppData\Roaming and AppData\LocalLow, where they exist.
+pub fn app_data_dirs(profile: &UserProfile) -> Vec<PathBuf> {
+    APP_DATA_DIRS
+        .iter()
+        .map(|parts| parts.iter().fold(profile.path.clone(), |path, part| path.join(part)))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+// The profile a path lives in, if any.
+pub fn owner<'a>(profiles: &'a [UserProfile], path: &Path) -> Option<&'a UserProfile> {
+    profiles.iter().find(|profile| path.starts_with(&profile.path))
+}
+
+// Expands `%VAR%` references, leaving unknown ones as they are.
+pub fn expand_env(text: &str) -> String {
+    expand_vars(text, |name| std::env::var(name).ok())
+}
+
+// Expands `%VAR%` references through `lookup`, leaving unknown ones as they are.
+pub fn expand_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('%') {
+        let Some(len) = rest[start + 1..].find('%') else { break };
+        let name = &rest[start + 1..start + 1 + len];
+        result.push_str(&rest[..start]);
+        match lookup(name) {
+            Some(value) if !name.is_empty() => result.push_str(&value),
+            _ => result.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+// --- User Hives ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserHive {
+    pub user: String,
+    // `HKEY_CURRENT_USER`, `HKEY_USERS\<SID>` or a mount point for a signed-out user.
+    pub root: String,
+}
+
+// The registry of every local user: HKCU for whoever runs Kuri (usually an admin),
+// `HKEY_USERS\<SID>` for everyone else who is signed in, and any hives in `mounted`.
+pub fn user_hives(registry: &dyn RegistryBackend, mounted: &MountedHives) -> Vec<UserHive> {
+    let profiles = list_profiles(registry);
+    let home = dirs::home_dir();
+    let current = profiles.iter().find(|p| Some(&p.path) == home.as_ref());
+    let user_name = |sid: &str| profiles.iter().find(|p| p.sid == sid).map_or_else(|| sid.to_string(), |p| p.name.clone());
+
+    let current_name = current
+        .map(|p| p.name.clone())
+        .or_else(|| home.as_ref().and_then(|h| h.file_name()).map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "current user".to_string());
+    let mut hives = vec![UserHive { user: current_name, root: "HKEY_CURRENT_USER".to_string() }];
+
+    for sid in registry.enum_keys("HKEY_USERS").unwrap_or_default() {
+        if is_user_sid(&sid) && current.is_none_or(|p| !p.sid.eq_ignore_ascii_case(&sid)) {
+            hives.push(UserHive { user: user_name(&sid), root: registry::join("HKEY_USERS", &sid) });
+        }
+    }
+    for sid in &mounted.sids {
+        hives.push(UserHive { user: user_name(sid), root: mount_point(sid) });
+    }
+    hives
+}
+
+fn mount_point(sid: &str) -> String {
+    registry::join("HKEY_USERS", &format!("{}{}", MOUNT_PREFIX, sid))
+}
+
+// Hives mounted for the lifetime of this value; they are unloaded on drop, because a
+// hive left loaded stops its owner from signing in with their normal profile.
+pub struct MountedHives {
+    registry: Arc<dyn RegistryBackend>,
+    sids: Vec<String>,
+}
+
+impl MountedHives {
+    pub fn none(registry: Arc<dyn RegistryBackend>) -> Self {
+        MountedHives { registry, sids: vec![] }
+    }
+
+    // Mounts the NTUSER.DAT of every profile whose hive isn't loaded, i.e. whose user is signed out.
+    pub fn signed_out_users(registry: Arc<dyn RegistryBackend>) -> Self {
+        let loaded = registry.enum_keys("HKEY_USERS").unwrap_or_default();
+        let sids = list_profiles(registry.as_ref())
+            .into_iter()
+            .filter(|p| !loaded.iter().any(|sid| sid.eq_ignore_ascii_case(&p.sid)))
+            .map(|p| p.sid)
+            .collect();
+        Self::mount(registry, sids)
+    }
+
+    // Mounts whichever signed-out users' hives `keys` live in, so they can be deleted or restored.
+    pub fn for_keys(registry: Arc<dyn RegistryBackend>, keys: &[String]) -> Self {
+        let prefix = format!("HKEY_USERS\\{}", MOUNT_PREFIX).to_lowercase();
+        let mut sids: Vec<String> = keys
+            .iter()
+            .filter(|key| key.to_lowercase().starts_with(&prefix))
+            .filter_map(|key| key[prefix.len()..].split('\\').next().map(str::to_string))
+            .collect();
+        sids.sort();
+        sids.dedup();
+        Self::mount(registry, sids)
+    }
+
+    fn mount(registry: Arc<dyn RegistryBackend>, sids: Vec<String>) -> Self {
+        let profiles = list_profiles(registry.as_ref());
+        let sids = sids
+            .into_iter()
+            .filter(|sid| {
+                let Some(profile) = profiles.iter().find(|p| &p.sid == sid) else { return false };
+                let name = format!("{}{}", MOUNT_PREFIX, sid);
+                // Already mounted (e.g. by an earlier run that crashed) counts as mounted.
+                registry.open_key(&mount_point(sid)).is_ok()
+                    || registry.load_hive(&name, &hive_file(&profile.path)).is_ok()
+            })
+            .collect();
+        MountedHives { registry, sids }
+    }
+}
+
+impl Drop for MountedHives {
+    fn drop(&mut self) {
+        for sid in &self.sids {
+            let _ = self.registry.unload_hive(&format!("{}{}", MOUNT_PREFIX, sid));
+        }
+    }
+}
+
+fn hive_file(profile: &Path) -> PathBuf {
+    profile.join("NTUSER.DAT")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    fn add_profile(reg: &MemoryRegistry, sid: &str, path: &str) {
+        let key = registry::join(PROFILE_LIST, sid);
+        reg.set_value(&key, "ProfileImagePath", &RegValue::ExpandString(path.into())).unwrap();
+    }
+
+    #[test]
+    fn lists_user_profiles_and_their_hives() {
+        let reg = MemoryRegistry::new();
+        add_profile(&reg, "S-1-5-18", r"C:\Windows\system32\config\systemprofile");
+        add_profile(&reg, "S-1-5-21-1-1001", r"C:\Users\alice");
+        add_profile(&reg, "S-1-5-21-1-1002", r"%KURI_TEST_PROFILES%\bob");
+        std::env::set_var("KURI_TEST_PROFILES", r"D:\Profiles");
+        for sid in ["S-1-5-18", "S-1-5-21-1-1001", "S-1-5-21-1-1001_Classes", "S-1-5-21-1-1003"] {
+            reg.create_key(&registry::join("HKEY_USERS", sid)).unwrap();
+        }
+
+        let profiles = list_profiles(&reg);
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        assert_eq!(profiles[1].path, PathBuf::from(r"D:\Profiles\bob"));
+
+        let reg: Arc<dyn RegistryBackend> = Arc::new(reg);
+        let hives = user_hives(reg.as_ref(), &MountedHives::none(reg.clone()));
+        let roots: Vec<(&str, &str)> = hives.iter().skip(1).map(|h| (h.user.as_str(), h.root.as_str())).collect();
+        assert_eq!(hives[0].root, "HKEY_CURRENT_USER");
+        assert_eq!(
+            roots,
+            [("alice", r"HKEY_USERS\S-1-5-21-1-1001"), ("S-1-5-21-1-1003", r"HKEY_USERS\S-1-5-21-1-1003")]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_folders_under_the_profiles_root() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["bob", "alice", "Public", "Default"] {
+            std::fs::create_dir_all(dir.path().join(name).join("AppData").join("Roaming")).unwrap();
+        }
+        std::fs::create_dir_all(dir.path().join("alice").join("AppData").join("Local")).unwrap();
+        std::fs::write(dir.path().join("desktop.ini"), "").unwrap();
+
+        let profiles = user_profiles(&MemoryRegistry::new(), Some(dir.path()));
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        assert_eq!(
+            app_data_dirs(&profiles[0]),
+            [dir.path().join("alice").join("AppData").join("Local"), dir.path().join("alice").join("AppData").join("Roaming")]
+        );
+
+        let file = dir.path().join("bob").join("AppData").join("Roaming").join("contoso.ini");
+        assert_eq!(owner(&profiles, &file).map(|p| p.name.as_str()), Some("bob"));
+        assert_eq!(owner(&profiles, Path::new("/opt/contoso")), None);
+    }
+
+    #[test]
+    fn mounts_only_hives_that_keys_refer_to() {
+        let reg = MemoryRegistry::new();
+        add_profile(&reg, "S-1-5-21-1-1002", r"C:\Users\bob");
+        // The in-memory registry can't load hives, so pre-create the mount point.
+        reg.create_key(r"HKEY_USERS\KuriUninstaller-S-1-5-21-1-1002\Software").unwrap();
+        let reg: Arc<dyn RegistryBackend> = Arc::new(reg);
+
+        let keys = vec![
+            r"HKEY_USERS\KuriUninstaller-S-1-5-21-1-1002\Software\Contoso".to_string(),
+            r"HKEY_USERS\KuriUninstaller-S-1-5-21-1-9999\Software\Contoso".to_string(),
+            r"HKEY_CURRENT_USER\Software\Contoso".to_string(),
+        ];
+        let mounted = MountedHives::for_keys(reg.clone(), &keys);
+        assert_eq!(mounted.sids, ["S-1-5-21-1-1002"]);
+
+        let hives = user_hives(reg.as_ref(), &mounted);
+        assert_eq!(hives.last().unwrap().user, "bob");
+        assert_eq!(hives.last().unwrap().root, r"HKEY_USERS\KuriUninstaller-S-1-5-21-1-1002");
+    }
+}
@@ -0,0 +1,279 @@
+use crate::known_folders::{KnownFolder, KnownFolders};
+use crate::profiles::{self, UserProfile};
+use crate::registry::{self, RegistryBackend};
+use crate::rules;
+use crate::scan::ScanOptions;
+use crate::FoundItem;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+    // The entry and everything above it, since deleting a parent takes the entry along.
+    Itself,
+    // The entry, everything above it and everything inside it.
+    Tree,
+    // The entry and everything above it. What's inside is open even within a protected
+    // tree: Windows keeps programs' own entries there, such as their scheduled tasks.
+    Container,
+}
+
+// Hives and system keys. Keys under `HKEY_USERS\<SID>` are checked as `HKEY_CURRENT_USER`,
+// and keys under `SOFTWARE\Wow6432Node` as their 64-bit counterparts.
+const PROTECTED_KEYS: [(&str, Protection); 36] = [
+    ("HKEY_LOCAL_MACHINE", Protection::Itself),
+    ("HKEY_CURRENT_USER", Protection::Itself),
+    ("HKEY_USERS", Protection::Itself),
+    ("HKEY_CLASSES_ROOT", Protection::Itself),
+    ("HKEY_CURRENT_CONFIG", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\BCD00000000", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\HARDWARE", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SAM", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SECURITY", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Classes", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Clients", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Policies", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths", Protection::Itself),
+    // Windows Installer's product and component registration; MsiExec keeps it in step.
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Installer", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Run", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\RunOnce", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Enum", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services", Protection::Itself),
+    // `CurrentControlSet` is a link to this one.
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Control", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Enum", Protection::Tree),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Services", Protection::Itself),
+    (r"HKEY_LOCAL_MACHINE\SYSTEM\Setup", Protection::Tree),
+    (r"HKEY_CURRENT_USER\Control Panel", Protection::Tree),
+    (r"HKEY_CURRENT_USER\Environment", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Classes", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Policies", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Microsoft\Windows NT", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Explorer", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\RunOnce", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Uninstall", Protection::Itself),
+    (r"HKEY_CURRENT_USER\Volatile Environment", Protection::Tree),
+];
+
+// Files, folders and registry keys that are never offered for deletion and never deleted,
+// whatever a search term happens to match.
+#[derive(Debug, Clone, Default)]
+pub struct ProtectedItems {
+    paths: Vec<(Vec<String>, Protection)>,
+    keys: Vec<(Vec<String>, Protection)>,
+}
+
+impl ProtectedItems {
+    // Windows itself, the system drive, every known folder, every profile and its AppData,
+    // and the registry's hives and system keys.
+    pub fn builtin(folders: &dyn KnownFolders, user_profiles: &[UserProfile]) -> Self {
+        let mut protected = ProtectedItems::default();
+        for var in ["SystemRoot", "windir"] {
+            if let Some(dir) = std::env::var_os(var) {
+                protected.protect_path(Path::new(&dir), Protection::Tree);
+            }
+        }
+        if let Some(drive) = std::env::var_os("SystemDrive") {
+            protected.protect_path(Path::new(&drive), Protection::Itself);
+        }
+
+        let known = KnownFolder::ALL.iter().filter_map(|&folder| folders.resolve(folder));
+        let personal = [dirs::home_dir(), dirs::desktop_dir(), dirs::document_dir(), dirs::download_dir()];
+        for dir in known.chain(personal.into_iter().flatten()) {
+            protected.protect_path(&dir, Protection::Itself);
+        }
+        for profile in user_profiles {
+            protected.protect_path(&profile.path.join("AppData"), Protection::Itself);
+            for dir in profiles::app_data_dirs(profile) {
+                protected.protect_path(&dir, Protection::Itself);
+            }
+        }
+
+        for (key, protection) in PROTECTED_KEYS {
+            protected.protect_key(key, protection);
+        }
+
+        if let Some(dir) = std::env::var_os("SystemRoot") {
+            protected.protect_path(&Path::new(&dir).join("System32").join("Tasks"), Protection::Container);
+        }
+        for index in rules::TASK_INDEXES.into_iter().chain(["Tree"]) {
+            protected.protect_key(&registry::join(rules::TASK_CACHE, index), Protection::Container);
+        }
+        protected
+    }
+
+    pub fn protect_path(&mut self, path: &Path, protection: Protection) {
+        self.paths.push((parts(&path.to_string_lossy()), protection));
+    }
+
+    pub fn protect_key(&mut self, key: &str, protection: Protection) {
+        self.keys.push((parts(key), protection));
+    }
+
+    // A user-configured entry, protected along with everything inside it. Registry keys
+    // are told apart from paths by their hive name.
+    pub fn protect_entry(&mut self, entry: &str) {
+        if entry.to_uppercase().starts_with("HKEY_") {
+            self.protect_key(entry, Protection::Tree);
+        } else {
+            self.protect_path(Path::new(entry), Protection::Tree);
+        }
+    }
+
+    pub fn is_protected(&self, item: &FoundItem) -> bool {
+        match item {
+            FoundItem::File(path) | FoundItem::Directory(path) => covers(&self.paths, &parts(&path.to_string_lossy())),
+            FoundItem::RegistryKey(key) => key_variants(key).iter().any(|key| covers(&self.keys, key)),
+            // A value is never above anything, so only the trees its key lies in protect it.
+            FoundItem::RegistryValue { key, name } => key_variants(key).iter().any(|key| {
+                let value: Vec<String> = key.iter().cloned().chain([name.to_lowercase()]).collect();
+                within_tree(&self.keys, &value)
+            }),
+        }
+    }
+}
+
+// The built-in list for this machine plus the user's own entries.
+pub fn system_protection(registry: &dyn RegistryBackend, folders: &dyn KnownFolders, options: &ScanOptions) -> ProtectedItems {
+    let user_profiles = profiles::user_profiles(registry, options.profiles_root.as_deref());
+    let mut protected = ProtectedItems::builtin(folders, &user_profiles);
+    if let Some(root) = &options.profiles_root {
+        protected.protect_path(root, Protection::Itself);
+    }
+    for entry in &options.protected {
+        protected.protect_entry(entry);
+    }
+    protected
+}
+
+fn covers(entries: &[(Vec<String>, Protection)], item: &[String]) -> bool {
+    entries.iter().any(|(entry, _)| entry.starts_with(item)) || within_tree(entries, item)
+}
+
+fn within_tree(entries: &[(Vec<String>, Protection)], item: &[String]) -> bool {
+    // A container inside a protected tree opens up what's inside it, but not what a
+    // tree further down protects again.
+    let opened = |tree: &[String]| {
+        entries.iter().any(|(entry, protection)| {
+            *protection == Protection::Container
+                && entry.len() > tree.len()
+                && entry.starts_with(tree)
+                && item.len() > entry.len()
+                && item.starts_with(entry)
+        })
+    };
+    entries
+        .iter()
+        .any(|(entry, protection)| *protection == Protection::Tree && item.starts_with(entry) && !opened(entry))
+}
+
+// Lowercase components, so `C:\Windows\` and `c:/windows` compare equal.
+fn parts(text: &str) -> Vec<String> {
+    text.split(['\\', '/']).filter(|part| !part.is_empty()).map(str::to_lowercase).collect()
+}
+
+fn key_variants(key: &str) -> Vec<Vec<String>> {
+    let mut key = parts(key);
+    // Every user's hive looks like HKEY_CURRENT_USER, whether loaded or mounted by us.
+    if key.len() >= 2 && key[0] == "hkey_users" {
+        key.splice(..2, ["hkey_current_user".to_string()]);
+    }
+    let mut variants = vec![key.clone()];
+    if key.len() >= 3 && key[0] == "hkey_local_machine" && key[1] == "software" && key[2] == "wow6432node" {
+        key.remove(2);
+        variants.push(key);
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn path(text: &str) -> FoundItem {
+        FoundItem::Directory(PathBuf::from(text))
+    }
+
+    fn key(text: &str) -> FoundItem {
+        FoundItem::RegistryKey(text.to_string())
+    }
+
+    #[test]
+    fn protects_paths_their_parents_and_trees() {
+        let mut protected = ProtectedItems::default();
+        protected.protect_path(Path::new(r"C:\Windows"), Protection::Tree);
+        protected.protect_path(Path::new(r"C:\Users\alice\AppData\Roaming"), Protection::Itself);
+
+        assert!(protected.is_protected(&path(r"C:\Windows")));
+        assert!(protected.is_protected(&path(r"c:\windows\System32\contoso.dll")));
+        assert!(protected.is_protected(&path(r"C:\")));
+        assert!(protected.is_protected(&path(r"C:\Users\alice\AppData\Roaming\")));
+        assert!(protected.is_protected(&path(r"C:\Users\alice")));
+        assert!(!protected.is_protected(&path(r"C:\Users\alice\AppData\Roaming\Contoso")));
+        assert!(!protected.is_protected(&path(r"C:\Windows Apps\Contoso")));
+        assert!(!protected.is_protected(&path(r"C:\Program Files\Contoso")));
+    }
+
+    #[test]
+    fn protects_system_keys_in_every_hive() {
+        let mut protected = ProtectedItems::default();
+        for (key, protection) in PROTECTED_KEYS {
+            protected.protect_key(key, protection);
+        }
+
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft")));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Microsoft")));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Fonts")));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\Session Manager")));
+        assert!(protected.is_protected(&key(r"HKEY_USERS\S-1-5-21-1-1001\Software")));
+        assert!(protected.is_protected(&key(r"HKEY_USERS\KuriUninstaller-S-1-5-21-1-1002")));
+        assert!(!protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso")));
+        assert!(!protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Contoso")));
+        assert!(!protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services\ContosoSvc")));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Control\Session Manager")));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Enum\USB")));
+        assert!(!protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SYSTEM\ControlSet001\Services\ContosoSvc")));
+        assert!(protected.is_protected(&key(
+            r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Installer\UserData\S-1-5-18\Products\ABCD"
+        )));
+        assert!(!protected.is_protected(&key(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso")));
+        assert!(!protected.is_protected(&key(
+            r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall\{1234}"
+        )));
+
+        let tree = registry::join(rules::TASK_CACHE, "Tree");
+        protected.protect_key(&tree, Protection::Container);
+        assert!(protected.is_protected(&key(&tree)));
+        assert!(protected.is_protected(&key(rules::TASK_CACHE)));
+        assert!(!protected.is_protected(&key(&registry::join(&tree, r"Contoso\Updater"))));
+        assert!(protected.is_protected(&key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Winlogon")));
+
+        // Values in a key that is only protected itself, such as `Run`, may go.
+        let value = |key: &str, name: &str| FoundItem::RegistryValue { key: key.into(), name: name.into() };
+        assert!(!protected.is_protected(&value(r"HKEY_USERS\S-1-5-21-1-1001\Software\Microsoft\Windows\CurrentVersion\Run", "Contoso")));
+        assert!(protected.is_protected(&value(r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Winlogon", "Shell")));
+    }
+
+    #[test]
+    fn user_entries_protect_everything_inside() {
+        let mut protected = ProtectedItems::default();
+        protected.protect_entry(r"D:\Work");
+        protected.protect_entry(r"hkey_current_user\Software\Contoso\Shared");
+        protected.protect_key(rules::TASK_CACHE, Protection::Tree);
+        protected.protect_key(&registry::join(rules::TASK_CACHE, "Tree"), Protection::Container);
+        protected.protect_entry(&registry::join(rules::TASK_CACHE, r"Tree\Contoso"));
+
+        assert!(protected.is_protected(&path(r"D:\Work\Contoso")));
+        assert!(protected.is_protected(&key(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso\Shared\Licenses")));
+        assert!(protected.is_protected(&key(r"HKEY_CURRENT_USER\Software\Contoso")));
+        assert!(!protected.is_protected(&key(r"HKEY_CURRENT_USER\Software\Contoso\Editor")));
+        assert!(protected.is_protected(&key(&registry::join(rules::TASK_CACHE, r"Tree\Contoso\Updater"))));
+        assert!(!protected.is_protected(&key(&registry::join(rules::TASK_CACHE, r"Tree\Fabrikam"))));
+    }
+}
@@ -0,0 +1,457 @@
+use crate::registry::{self, RegValue, RegistryBackend};
+use std::io;
+use std::io::ErrorKind;
+
+// --- .reg Export ---
+
+const HEADER: &str = "Windows Registry Editor Version 5.00";
+// regedit wraps long hex values so that no line grows much past 80 columns.
+const HEX_LINE_WIDTH: usize = 76;
+
+// Serializes each key and its whole subtree in the format regedit writes and imports.
+pub fn export_keys(registry: &dyn RegistryBackend, keys: &[String]) -> io::Result<String> {
+    export(registry, keys, &[])
+}
+
+// Keys with their subtrees, then single `(key, value name)` values, in one file.
+pub fn export(registry: &dyn RegistryBackend, keys: &[String], values: &[(String, String)]) -> io::Result<String> {
+    let mut out = format!("{}\r\n\r\n", HEADER);
+    for key in keys {
+        export_key(registry, key, &mut out)?;
+    }
+    for (key, name) in values {
+        let value = registry.get_value(key, name)?;
+        out.push_str(&format!("[{}]\r\n{}\r\n\r\n", key, format_value(name, &value)));
+    }
+    Ok(out)
+}
+
+fn export_key(registry: &dyn RegistryBackend, path: &str, out: &mut String) -> io::Result<()> {
+    out.push_str(&format!("[{}]\r\n", path));
+    for (name, value) in registry.enum_values(path)? {
+        out.push_str(&format_value(&name, &value));
+        out.push_str("\r\n");
+    }
+    out.push_str("\r\n");
+
+    for subkey in registry.enum_keys(path)? {
+        export_key(registry, &registry::join(path, &subkey), out)?;
+    }
+    Ok(())
+}
+
+fn format_value(name: &str, value: &RegValue) -> String {
+    let prefix = if name.is_empty() {
+        String::from("@=")
+    } else {
+        format!("\"{}\"=", escape(name))
+    };
+
+    match value {
+        RegValue::String(s) => format!("{}\"{}\"", prefix, escape(s)),
+        RegValue::Dword(d) => format!("{}dword:{:08x}", prefix, d),
+        RegValue::ExpandString(s) => format_hex(&prefix, "hex(2):", &utf16_bytes(&[s.as_str()], false)),
+        RegValue::MultiString(list) => {
+            let parts: Vec<&str> = list.iter().map(String::as_str).collect();
+            format_hex(&prefix, "hex(7):", &utf16_bytes(&parts, true))
+        }
+        RegValue::Qword(q) => format_hex(&prefix, "hex(b):", &q.to_le_bytes()),
+        RegValue::Binary(bytes) => format_hex(&prefix, "hex:", bytes),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Each string is NUL-terminated; REG_MULTI_SZ adds one more NUL to close the list.
+fn utf16_bytes(strings: &[&str], multi: bool) -> Vec<u8> {
+    let mut units: Vec<u16> = Vec::new();
+    for s in strings {
+        units.extend(s.encode_utf16());
+        units.push(0);
+    }
+    if multi {
+        units.push(0);
+    }
+    units.iter().flat_map(|u| u.to_le_bytes()).collect()
+}
+
+fn format_hex(prefix: &str, kind: &str, bytes: &[u8]) -> String {
+    let mut out = format!("{}{}", prefix, kind);
+    let mut line_len = out.len();
+    for (i, byte) in bytes.iter().enumerate() {
+        out.push_str(&format!("{:02x}", byte));
+        line_len += 2;
+        if i + 1 < bytes.len() {
+            out.push(',');
+            line_len += 1;
+            if line_len >= HEX_LINE_WIDTH {
+                out.push_str("\\\r\n  ");
+                line_len = 2;
+            }
+        }
+    }
+    out
+}
+
+// regedit expects UTF-16LE with a byte order mark for version 5.00 files.
+pub fn encode_utf16le(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+    bytes
+}
+
+// --- .reg Import ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegOperation {
+    CreateKey(String),
+    DeleteKey(String),
+    SetValue { key: String, name: String, value: RegValue },
+    DeleteValue { key: String, name: String },
+}
+
+// Accepts UTF-16LE (as regedit writes) and UTF-8, with or without a byte order mark.
+pub fn decode(bytes: &[u8]) -> Result<String, String> {
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        if rest.len() % 2 != 0 {
+            return Err("Truncated UTF-16 registry file".to_string());
+        }
+        let units: Vec<u16> = rest.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        return String::from_utf16(&units).map_err(|e| e.to_string());
+    }
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+}
+
+pub fn parse(text: &str) -> Result<Vec<RegOperation>, String> {
+    let mut lines = logical_lines(text).into_iter();
+
+    match lines.find(|(_, line)| !line.is_empty()) {
+        Some((_, header)) if header == HEADER || header == "REGEDIT4" => {}
+        _ => return Err("Not a registry file: missing \"Windows Registry Editor\" header".to_string()),
+    }
+
+    let mut ops = Vec::new();
+    let mut current_key: Option<String> = None;
+
+    for (line_no, line) in lines {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let fail = |msg: &str| format!("Line {}: {}", line_no, msg);
+
+        if let Some(inner) = line.strip_prefix('[') {
+            let path = inner.strip_suffix(']').ok_or_else(|| fail("unterminated key name"))?;
+            if let Some(path) = path.strip_prefix('-') {
+                ops.push(RegOperation::DeleteKey(path.to_string()));
+                current_key = None;
+            } else {
+                ops.push(RegOperation::CreateKey(path.to_string()));
+                current_key = Some(path.to_string());
+            }
+            continue;
+        }
+
+        let key = current_key.clone().ok_or_else(|| fail("value outside of a key"))?;
+        let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+            (String::new(), rest)
+        } else {
+            parse_quoted(&line).ok_or_else(|| fail("malformed value name"))?
+        };
+        let data = rest.trim_start().strip_prefix('=').ok_or_else(|| fail("expected '='"))?.trim();
+
+        if data == "-" {
+            ops.push(RegOperation::DeleteValue { key, name });
+        } else {
+            let value = parse_data(data).map_err(|e| fail(&e))?;
+            ops.push(RegOperation::SetValue { key, name, value });
+        }
+    }
+
+    Ok(ops)
+}
+
+// Joins `\`-continued lines and numbers each logical line by where it started.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (i, raw) in text.lines().enumerate() {
+        let raw = raw.trim_end();
+        let (line_no, mut line) = match pending.take() {
+            Some((n, mut acc)) => {
+                acc.push_str(raw.trim_start());
+                (n, acc)
+            }
+            None => (i + 1, raw.to_string()),
+        };
+        if line.ends_with('\\') && !line.starts_with('[') {
+            line.pop();
+            pending = Some((line_no, line));
+        } else {
+            lines.push((line_no, line));
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+// Reads a `"..."` token from the start of `s`, returning the unescaped text and the remainder.
+fn parse_quoted(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut out = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?.1),
+            '"' => return Some((out, &s[i + 2..])),
+            _ => out.push(c),
+        }
+    }
+    None
+}
+
+fn parse_data(data: &str) -> Result<RegValue, String> {
+    if data.starts_with('"') {
+        let (s, rest) = parse_quoted(data).ok_or("unterminated string")?;
+        if !rest.trim().is_empty() {
+            return Err("unexpected text after string".to_string());
+        }
+        return Ok(RegValue::String(s));
+    }
+    if let Some(hex) = data.strip_prefix("dword:") {
+        return u32::from_str_radix(hex.trim(), 16)
+            .map(RegValue::Dword)
+            .map_err(|_| format!("invalid dword '{}'", hex));
+    }
+
+    let (kind, bytes) = if let Some(rest) = data.strip_prefix("hex:") {
+        (3, rest)
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (kind, rest) = rest.split_once("):").ok_or("malformed hex type")?;
+        let kind = u32::from_str_radix(kind, 16).map_err(|_| format!("invalid hex type '{}'", kind))?;
+        (kind, rest)
+    } else {
+        return Err(format!("unrecognised value data '{}'", data));
+    };
+    let bytes = parse_hex_bytes(bytes)?;
+
+    Ok(match kind {
+        1 => RegValue::String(utf16_strings(&bytes).into_iter().next().unwrap_or_default()),
+        2 => RegValue::ExpandString(utf16_strings(&bytes).into_iter().next().unwrap_or_default()),
+        7 => RegValue::MultiString(utf16_strings(&bytes)),
+        4 if bytes.len() == 4 => RegValue::Dword(u32::from_le_bytes(bytes[..4].try_into().unwrap())),
+        0xb if bytes.len() == 8 => RegValue::Qword(u64::from_le_bytes(bytes[..8].try_into().unwrap())),
+        // Anything else (REG_NONE, REG_LINK, resource lists, ...) is kept as raw bytes.
+        _ => RegValue::Binary(bytes),
+    })
+}
+
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| u8::from_str_radix(b, 16).map_err(|_| format!("invalid hex byte '{}'", b)))
+        .collect()
+}
+
+fn utf16_strings(bytes: &[u8]) -> Vec<String> {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    units
+        .split(|&u| u == 0)
+        .filter(|s| !s.is_empty())
+        .map(String::from_utf16_lossy)
+        .collect()
+}
+
+// Replays parsed operations through the registry layer. Deleting something that is
+// already gone is not an error, matching regedit's behaviour.
+pub fn apply(registry: &dyn RegistryBackend, ops: &[RegOperation]) -> Result<(), String> {
+    let mut errors = Vec::new();
+    let ignore_missing = |r: io::Result<()>| match r {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        other => other,
+    };
+
+    for op in ops {
+        let (result, target) = match op {
+            RegOperation::CreateKey(key) => (registry.create_key(key), key.clone()),
+            RegOperation::DeleteKey(key) => (ignore_missing(registry.delete_subtree(key)), key.clone()),
+            RegOperation::SetValue { key, name, value } => {
+                (registry.set_value(key, name, value), format!("{}\\{}", key, name))
+            }
+            RegOperation::DeleteValue { key, name } => {
+                (ignore_missing(registry.delete_value(key, name)), format!("{}\\{}", key, name))
+            }
+        };
+        if let Err(e) = result {
+            errors.push(format!("Failed to restore {}: {}", target, e));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+
+    #[test]
+    fn exports_every_value_type() {
+        let reg = MemoryRegistry::new();
+        let key = r"HKEY_CURRENT_USER\Software\Contoso";
+        reg.set_value(key, "", &RegValue::String("default".into())).unwrap();
+        reg.set_value(key, "Path", &RegValue::String(r#"C:\Program Files\"Contoso""#.into())).unwrap();
+        reg.set_value(key, "Expand", &RegValue::ExpandString("%A%".into())).unwrap();
+        reg.set_value(key, "Multi", &RegValue::MultiString(vec!["a".into(), "b".into()])).unwrap();
+        reg.set_value(key, "Count", &RegValue::Dword(0x2a)).unwrap();
+        reg.set_value(key, "Big", &RegValue::Qword(0x0102030405060708)).unwrap();
+        reg.set_value(key, "Blob", &RegValue::Binary(vec![0xde, 0xad])).unwrap();
+        reg.set_value(&registry::join(key, "Child"), "Flag", &RegValue::Dword(1)).unwrap();
+
+        let text = export_keys(&reg, &[key.to_string()]).unwrap();
+
+        let expected = [
+            "Windows Registry Editor Version 5.00",
+            "",
+            r"[HKEY_CURRENT_USER\Software\Contoso]",
+            "@=\"default\"",
+            r#""Path"="C:\\Program Files\\\"Contoso\"""#,
+            "\"Expand\"=hex(2):25,00,41,00,25,00,00,00",
+            "\"Multi\"=hex(7):61,00,00,00,62,00,00,00,00,00",
+            "\"Count\"=dword:0000002a",
+            "\"Big\"=hex(b):08,07,06,05,04,03,02,01",
+            "\"Blob\"=hex:de,ad",
+            "",
+            r"[HKEY_CURRENT_USER\Software\Contoso\Child]",
+            "\"Flag\"=dword:00000001",
+            "",
+            "",
+        ]
+        .join("\r\n");
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn exports_single_values_without_their_siblings() {
+        let reg = MemoryRegistry::new();
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        reg.set_value(run, "Contoso", &RegValue::String(r"C:\Contoso\tray.exe".into())).unwrap();
+        reg.set_value(run, "Fabrikam", &RegValue::String(r"C:\Fabrikam\agent.exe".into())).unwrap();
+
+        let text = export(&reg, &[], &[(run.to_string(), "Contoso".to_string())]).unwrap();
+        assert_eq!(
+            parse(&text).unwrap(),
+            [
+                RegOperation::CreateKey(run.into()),
+                RegOperation::SetValue { key: run.into(), name: "Contoso".into(), value: RegValue::String(r"C:\Contoso\tray.exe".into()) },
+            ]
+        );
+        assert!(export(&reg, &[], &[(run.to_string(), "Missing".to_string())]).is_err());
+    }
+
+    #[test]
+    fn wraps_long_hex_values() {
+        let line = format_value("Blob", &RegValue::Binary(vec![0xab; 40]));
+        let lines: Vec<&str> = line.split("\r\n").collect();
+
+        assert!(lines.len() > 1);
+        assert!(lines[..lines.len() - 1].iter().all(|l| l.ends_with(",\\") && l.len() <= 80));
+        assert!(lines[1..].iter().all(|l| l.starts_with("  ")));
+        assert_eq!(line.matches("ab").count(), 40);
+    }
+
+    #[test]
+    fn missing_key_is_an_error() {
+        let reg = MemoryRegistry::new();
+        assert!(export_keys(&reg, &[r"HKEY_CURRENT_USER\Software\Nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_memory_registry() {
+        let source = MemoryRegistry::new();
+        let key = r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso";
+        source.set_value(key, "", &RegValue::String("default".into())).unwrap();
+        source.set_value(key, "Quote", &RegValue::String(r#"say "hi" \ bye"#.into())).unwrap();
+        source.set_value(key, "Expand", &RegValue::ExpandString("%ProgramFiles%\\Contoso".into())).unwrap();
+        source.set_value(key, "Multi", &RegValue::MultiString(vec!["one".into(), "two".into()])).unwrap();
+        source.set_value(key, "Dword", &RegValue::Dword(u32::MAX)).unwrap();
+        source.set_value(key, "Qword", &RegValue::Qword(1 << 40)).unwrap();
+        source.set_value(key, "Blob", &RegValue::Binary((0..=255).collect())).unwrap();
+        source.set_value(&registry::join(key, r"Deep\Er"), "Name", &RegValue::String("x".into())).unwrap();
+        source.create_key(&registry::join(key, "Empty")).unwrap();
+
+        let exported = export_keys(&source, &[key.to_string()]).unwrap();
+        let ops = parse(&decode(&encode_utf16le(&exported)).unwrap()).unwrap();
+
+        let target = MemoryRegistry::new();
+        apply(&target, &ops).unwrap();
+
+        assert_eq!(export_keys(&target, &[key.to_string()]).unwrap(), exported);
+    }
+
+    #[test]
+    fn parses_utf8_continuations_and_deletion_markers() {
+        let text = "Windows Registry Editor Version 5.00\n\n\
+            ; comment\n\
+            [-HKEY_CURRENT_USER\\Software\\Old]\n\n\
+            [HKEY_CURRENT_USER\\Software\\New]\n\
+            \"Gone\"=-\n\
+            \"Path\"=hex(2):43,00,3a,00,\\\n  5c,00,00,00\n\
+            \"List\"=hex(7):61,00,00,00,00,00\n\
+            \"Big\"=hex(b):01,00,00,00,00,00,00,00\n\
+            \"Raw\"=hex(0):\n";
+        let bytes = [&[0xEF, 0xBB, 0xBF][..], text.as_bytes()].concat();
+        let ops = parse(&decode(&bytes).unwrap()).unwrap();
+
+        let key = r"HKEY_CURRENT_USER\Software\New".to_string();
+        assert_eq!(
+            ops,
+            [
+                RegOperation::DeleteKey(r"HKEY_CURRENT_USER\Software\Old".into()),
+                RegOperation::CreateKey(key.clone()),
+                RegOperation::DeleteValue { key: key.clone(), name: "Gone".into() },
+                RegOperation::SetValue { key: key.clone(), name: "Path".into(), value: RegValue::ExpandString(r"C:\".into()) },
+                RegOperation::SetValue { key: key.clone(), name: "List".into(), value: RegValue::MultiString(vec!["a".into()]) },
+                RegOperation::SetValue { key: key.clone(), name: "Big".into(), value: RegValue::Qword(1) },
+                RegOperation::SetValue { key, name: "Raw".into(), value: RegValue::Binary(vec![]) },
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_honours_deletion_markers() {
+        let reg = MemoryRegistry::new();
+        reg.set_value(r"HKEY_CURRENT_USER\Software\Old\Sub", "A", &RegValue::Dword(1)).unwrap();
+        reg.set_value(r"HKEY_CURRENT_USER\Software\New", "Gone", &RegValue::Dword(1)).unwrap();
+
+        let ops = parse(
+            "REGEDIT4\n[-HKEY_CURRENT_USER\\Software\\Old]\n[-HKEY_CURRENT_USER\\Software\\Missing]\n\
+             [HKEY_CURRENT_USER\\Software\\New]\n\"Gone\"=-\n\"Never\"=-\n",
+        )
+        .unwrap();
+        apply(&reg, &ops).unwrap();
+
+        assert_eq!(reg.enum_keys(r"HKEY_CURRENT_USER\Software").unwrap(), ["New"]);
+        assert!(reg.enum_values(r"HKEY_CURRENT_USER\Software\New").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(parse("hello").is_err());
+        assert!(parse("REGEDIT4\n\"orphan\"=dword:1\n").is_err());
+        assert!(parse("REGEDIT4\n[HKEY_CURRENT_USER\\X]\n\"a\"=dword:zz\n").is_err());
+        assert!(decode(&[0xFF, 0xFE, 0x41]).is_err());
+    }
+
+    #[test]
+    fn utf16_output_has_bom() {
+        assert_eq!(encode_utf16le("A"), [0xFF, 0xFE, 0x41, 0x00]);
+    }
+}
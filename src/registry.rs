@@ -0,0 +1,385 @@
+#[cfg(any(test, not(windows)))]
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+#[cfg(any(test, not(windows)))]
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// --- Registry Values ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegValue {
+    String(String),
+    ExpandString(String),
+    MultiString(Vec<String>),
+    Dword(u32),
+    Qword(u64),
+    Binary(Vec<u8>),
+}
+
+impl RegValue {
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            RegValue::String(s) | RegValue::ExpandString(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+// --- Backend Trait ---
+
+// Every path handed to a backend is a full key path starting with the hive name,
+// e.g. `HKEY_LOCAL_MACHINE\SOFTWARE\Vendor`. Key names are case-insensitive.
+pub trait RegistryBackend: Send + Sync {
+    fn open_key(&self, path: &str) -> io::Result<()>;
+    fn enum_keys(&self, path: &str) -> io::Result<Vec<String>>;
+    fn enum_values(&self, path: &str) -> io::Result<Vec<(String, RegValue)>>;
+    fn get_value(&self, path: &str, name: &str) -> io::Result<RegValue>;
+    fn delete_subtree(&self, path: &str) -> io::Result<()>;
+    fn create_key(&self, path: &str) -> io::Result<()>;
+    fn set_value(&self, path: &str, name: &str, value: &RegValue) -> io::Result<()>;
+    fn delete_value(&self, path: &str, name: &str) -> io::Result<()>;
+
+    // When the key or one of its values was last changed.
+    fn last_write_time(&self, _path: &str) -> io::Result<SystemTime> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Last-write times are not supported by this registry"))
+    }
+
+    fn get_string(&self, path: &str, name: &str) -> Option<String> {
+        self.get_value(path, name)
+            .ok()
+            .and_then(|v| v.as_string().map(str::to_string))
+    }
+
+    // Mounts a hive file, such as a signed-out user's NTUSER.DAT, at `HKEY_USERS\<name>`.
+    fn load_hive(&self, _name: &str, _file: &Path) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Loading hives is not supported by this registry"))
+    }
+
+    fn unload_hive(&self, _name: &str) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Unloading hives is not supported by this registry"))
+    }
+}
+
+pub fn join(path: &str, child: &str) -> String {
+    format!("{}\\{}", path, child)
+}
+
+#[cfg(windows)]
+pub fn default_backend() -> Arc<dyn RegistryBackend> {
+    Arc::new(WinRegistry)
+}
+
+// There is no registry off Windows, so the GUI simply sees an empty one.
+#[cfg(not(windows))]
+pub fn default_backend() -> Arc<dyn RegistryBackend> {
+    Arc::new(MemoryRegistry::new())
+}
+
+#[cfg(any(test, not(windows)))]
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("Registry key not found: {}", path))
+}
+
+// --- In-Memory Implementation ---
+
+// Stands in for the registry off Windows and in tests.
+#[cfg(any(test, not(windows)))]
+#[derive(Debug, Default)]
+struct MemKey {
+    name: String,
+    // Keyed by lowercase name so lookups are case-insensitive but enumeration keeps the original case.
+    subkeys: BTreeMap<String, MemKey>,
+    values: Vec<(String, RegValue)>,
+    last_write: Option<SystemTime>,
+}
+
+#[cfg(any(test, not(windows)))]
+impl MemKey {
+    fn find(&self, path: &str) -> Option<&MemKey> {
+        path.split('\\')
+            .try_fold(self, |key, part| key.subkeys.get(&part.to_lowercase()))
+    }
+
+    fn find_or_create(&mut self, path: &str) -> &mut MemKey {
+        path.split('\\').fold(self, |key, part| {
+            key.subkeys
+                .entry(part.to_lowercase())
+                .or_insert_with(|| MemKey { name: part.to_string(), last_write: Some(SystemTime::now()), ..Default::default() })
+        })
+    }
+}
+
+#[cfg(any(test, not(windows)))]
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    root: Mutex<MemKey>,
+}
+
+#[cfg(any(test, not(windows)))]
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(any(test, not(windows)))]
+impl RegistryBackend for MemoryRegistry {
+    fn open_key(&self, path: &str) -> io::Result<()> {
+        self.root.lock().unwrap().find(path).map(|_| ()).ok_or_else(|| not_found(path))
+    }
+
+    fn enum_keys(&self, path: &str) -> io::Result<Vec<String>> {
+        let root = self.root.lock().unwrap();
+        let key = root.find(path).ok_or_else(|| not_found(path))?;
+        Ok(key.subkeys.values().map(|k| k.name.clone()).collect())
+    }
+
+    fn enum_values(&self, path: &str) -> io::Result<Vec<(String, RegValue)>> {
+        let root = self.root.lock().unwrap();
+        let key = root.find(path).ok_or_else(|| not_found(path))?;
+        Ok(key.values.clone())
+    }
+
+    fn get_value(&self, path: &str, name: &str) -> io::Result<RegValue> {
+        let root = self.root.lock().unwrap();
+        let key = root.find(path).ok_or_else(|| not_found(path))?;
+        key.values
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Value not found: {}", name)))
+    }
+
+    fn delete_subtree(&self, path: &str) -> io::Result<()> {
+        let (parent_path, key_name) = path
+            .rsplit_once('\\')
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Cannot delete a registry hive"))?;
+        let mut root = self.root.lock().unwrap();
+        let parent = parent_path
+            .split('\\')
+            .try_fold(&mut *root, |key, part| key.subkeys.get_mut(&part.to_lowercase()))
+            .ok_or_else(|| not_found(path))?;
+        parent
+            .subkeys
+            .remove(&key_name.to_lowercase())
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn create_key(&self, path: &str) -> io::Result<()> {
+        self.root.lock().unwrap().find_or_create(path);
+        Ok(())
+    }
+
+    fn set_value(&self, path: &str, name: &str, value: &RegValue) -> io::Result<()> {
+        let mut root = self.root.lock().unwrap();
+        let key = root.find_or_create(path);
+        key.last_write = Some(SystemTime::now());
+        match key.values.iter_mut().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            Some(existing) => existing.1 = value.clone(),
+            None => key.values.push((name.to_string(), value.clone())),
+        }
+        Ok(())
+    }
+
+    fn delete_value(&self, path: &str, name: &str) -> io::Result<()> {
+        let mut root = self.root.lock().unwrap();
+        let key = path
+            .split('\\')
+            .try_fold(&mut *root, |key, part| key.subkeys.get_mut(&part.to_lowercase()))
+            .ok_or_else(|| not_found(path))?;
+        let before = key.values.len();
+        key.values.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        if key.values.len() == before {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("Value not found: {}", name)));
+        }
+        key.last_write = Some(SystemTime::now());
+        Ok(())
+    }
+
+    fn last_write_time(&self, path: &str) -> io::Result<SystemTime> {
+        let root = self.root.lock().unwrap();
+        let key = root.find(path).ok_or_else(|| not_found(path))?;
+        Ok(key.last_write.unwrap_or(SystemTime::UNIX_EPOCH))
+    }
+}
+
+// --- Windows Implementation ---
+
+#[cfg(windows)]
+pub struct WinRegistry;
+
+#[cfg(windows)]
+impl WinRegistry {
+    fn open(path: &str, flags: u32) -> io::Result<winreg::RegKey> {
+        use winreg::enums::*;
+
+        let (hive_str, sub_path) = path.split_once('\\').unwrap_or((path, ""));
+        let hive = match hive_str {
+            "HKEY_LOCAL_MACHINE" => HKEY_LOCAL_MACHINE,
+            "HKEY_CURRENT_USER" => HKEY_CURRENT_USER,
+            "HKEY_USERS" => HKEY_USERS,
+            "HKEY_CLASSES_ROOT" => HKEY_CLASSES_ROOT,
+            "HKEY_CURRENT_CONFIG" => HKEY_CURRENT_CONFIG,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown registry hive in path: {}", path),
+                ))
+            }
+        };
+        winreg::RegKey::predef(hive).open_subkey_with_flags(sub_path, flags)
+    }
+
+    fn convert(value: winreg::RegValue) -> RegValue {
+        use winreg::enums::RegType::*;
+
+        let utf16 = |bytes: &[u8]| -> Vec<u16> {
+            bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect()
+        };
+        let string = |bytes: &[u8]| -> String {
+            let wide = utf16(bytes);
+            let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+            String::from_utf16_lossy(&wide[..end])
+        };
+
+        match value.vtype {
+            REG_SZ => RegValue::String(string(&value.bytes)),
+            REG_EXPAND_SZ => RegValue::ExpandString(string(&value.bytes)),
+            REG_MULTI_SZ => RegValue::MultiString(
+                String::from_utf16_lossy(&utf16(&value.bytes))
+                    .split('\0')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            REG_DWORD if value.bytes.len() >= 4 => {
+                RegValue::Dword(u32::from_le_bytes(value.bytes[..4].try_into().unwrap()))
+            }
+            REG_QWORD if value.bytes.len() >= 8 => {
+                RegValue::Qword(u64::from_le_bytes(value.bytes[..8].try_into().unwrap()))
+            }
+            _ => RegValue::Binary(value.bytes),
+        }
+    }
+
+    fn to_raw(value: &RegValue) -> winreg::RegValue {
+        use winreg::enums::RegType::*;
+
+        let utf16 = |strings: &[&str], multi: bool| -> Vec<u8> {
+            let mut units: Vec<u16> = Vec::new();
+            for s in strings {
+                units.extend(s.encode_utf16());
+                units.push(0);
+            }
+            if multi {
+                units.push(0);
+            }
+            units.iter().flat_map(|u| u.to_le_bytes()).collect()
+        };
+
+        let (vtype, bytes) = match value {
+            RegValue::String(s) => (REG_SZ, utf16(&[s.as_str()], false)),
+            RegValue::ExpandString(s) => (REG_EXPAND_SZ, utf16(&[s.as_str()], false)),
+            RegValue::MultiString(list) => {
+                let parts: Vec<&str> = list.iter().map(String::as_str).collect();
+                (REG_MULTI_SZ, utf16(&parts, true))
+            }
+            RegValue::Dword(d) => (REG_DWORD, d.to_le_bytes().to_vec()),
+            RegValue::Qword(q) => (REG_QWORD, q.to_le_bytes().to_vec()),
+            RegValue::Binary(b) => (REG_BINARY, b.clone()),
+        };
+        winreg::RegValue { bytes, vtype }
+    }
+}
+
+#[cfg(windows)]
+impl RegistryBackend for WinRegistry {
+    fn open_key(&self, path: &str) -> io::Result<()> {
+        Self::open(path, winreg::enums::KEY_READ).map(|_| ())
+    }
+
+    fn enum_keys(&self, path: &str) -> io::Result<Vec<String>> {
+        let key = Self::open(path, winreg::enums::KEY_READ)?;
+        Ok(key.enum_keys().filter_map(Result::ok).collect())
+    }
+
+    fn enum_values(&self, path: &str) -> io::Result<Vec<(String, RegValue)>> {
+        let key = Self::open(path, winreg::enums::KEY_READ)?;
+        Ok(key
+            .enum_values()
+            .filter_map(Result::ok)
+            .map(|(name, value)| (name, Self::convert(value)))
+            .collect())
+    }
+
+    fn get_value(&self, path: &str, name: &str) -> io::Result<RegValue> {
+        let key = Self::open(path, winreg::enums::KEY_READ)?;
+        key.get_raw_value(name).map(Self::convert)
+    }
+
+    fn delete_subtree(&self, path: &str) -> io::Result<()> {
+        let (parent_path, key_name) = path
+            .rsplit_once('\\')
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Cannot delete a registry hive"))?;
+        let parent = Self::open(parent_path, winreg::enums::KEY_WRITE).map_err(|e| {
+            io::Error::new(e.kind(), format!("Could not open parent key for: {}", path))
+        })?;
+        parent.delete_subkey_all(key_name)
+    }
+
+    fn create_key(&self, path: &str) -> io::Result<()> {
+        let (hive_path, sub_path) = path.split_once('\\').unwrap_or((path, ""));
+        Self::open(hive_path, winreg::enums::KEY_ALL_ACCESS)?
+            .create_subkey(sub_path)
+            .map(|_| ())
+    }
+
+    fn set_value(&self, path: &str, name: &str, value: &RegValue) -> io::Result<()> {
+        let key = Self::open(path, winreg::enums::KEY_SET_VALUE)?;
+        key.set_raw_value(name, &Self::to_raw(value))
+    }
+
+    fn delete_value(&self, path: &str, name: &str) -> io::Result<()> {
+        let key = Self::open(path, winreg::enums::KEY_SET_VALUE)?;
+        key.delete_value(name)
+    }
+
+    fn last_write_time(&self, path: &str) -> io::Result<SystemTime> {
+        // FILETIME counts 100ns intervals since 1601; the Unix epoch is this many of them later.
+        const UNIX_EPOCH_FILETIME: u64 = 116_444_736_000_000_000;
+        let info = Self::open(path, winreg::enums::KEY_READ)?.query_info()?;
+        let filetime = (u64::from(info.last_write_time.dwHighDateTime) << 32) | u64::from(info.last_write_time.dwLowDateTime);
+        let since_epoch = std::time::Duration::from_nanos(filetime.saturating_sub(UNIX_EPOCH_FILETIME) * 100);
+        Ok(SystemTime::UNIX_EPOCH + since_epoch)
+    }
+
+    // reg.exe enables the backup/restore privileges RegLoadKey needs on its own.
+    fn load_hive(&self, name: &str, file: &Path) -> io::Result<()> {
+        let mount = join("HKU", name);
+        Self::reg_exe(&["load".as_ref(), mount.as_ref(), file.as_os_str()])
+    }
+
+    fn unload_hive(&self, name: &str) -> io::Result<()> {
+        let mount = join("HKU", name);
+        Self::reg_exe(&["unload".as_ref(), mount.as_ref()])
+    }
+}
+
+#[cfg(windows)]
+impl WinRegistry {
+    fn reg_exe(args: &[&std::ffi::OsStr]) -> io::Result<()> {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+        let output = std::process::Command::new("reg.exe").args(args).creation_flags(CREATE_NO_WINDOW).output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        }
+    }
+}
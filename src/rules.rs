@@ -0,0 +1,345 @@
+use crate::known_folders::{KnownFolder, KnownFolders};
+use crate::profiles::{self, UserHive, UserProfile};
+use crate::registry::{self, RegistryBackend};
+use crate::{FoundItem, ProgramInfo};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::fs;
+use std::path::PathBuf;
+
+// Rules shipped with Kuri, for programs that keep things where name matching can't find them.
+const BUILTIN_RULES: [(&str, &str); 1] = [("chrome.toml", include_str!("../rules/chrome.toml"))];
+
+const SERVICES: &str = r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services";
+pub const TASK_CACHE: &str = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Schedule\TaskCache";
+// The keys below `TASK_CACHE` that index a scheduled task by its id.
+pub const TASK_INDEXES: [&str; 5] = ["Tasks", "Plain", "Logon", "Boot", "Maintenance"];
+// Per-user folders a rule path can start from, and where they sit in a profile.
+const USER_VARS: [(&str, &[&str]); 3] =
+    [("USERPROFILE", &[]), ("APPDATA", &["AppData", "Roaming"]), ("LOCALAPPDATA", &["AppData", "Local"])];
+
+// --- Rule Files ---
+
+// A rule file as written, e.g.
+//
+//     display_name = '^Google Chrome$'
+//     paths = ['%LOCALAPPDATA%\Google\Chrome\**']
+//     registry = ['HKEY_CURRENT_USER\Software\Google\Chrome']
+//     services = ['GoogleChromeElevationService']
+//     scheduled_tasks = ['\Contoso\Updater']
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RuleFile {
+    // Case-insensitive regular expressions; the rule applies when each one given matches.
+    display_name: Option<String>,
+    publisher: Option<String>,
+    // Globs with `%VAR%` references; a trailing `\**` stands for the folder and all it holds.
+    paths: Vec<String>,
+    // `HKEY_CURRENT_USER` keys are looked up in every user's hive.
+    registry: Vec<String>,
+    services: Vec<String>,
+    scheduled_tasks: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AppRule {
+    // The file the rule was read from.
+    pub source: String,
+    display_name: Option<Regex>,
+    publisher: Option<Regex>,
+    file: RuleFile,
+}
+
+impl AppRule {
+    // Parses a `.toml` or `.json` rule file.
+    pub fn parse(source: &str, text: &str) -> Result<Self, String> {
+        let file: RuleFile = if source.to_lowercase().ends_with(".json") {
+            serde_json::from_str(text).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(text).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("Invalid rule file {}: {}", source, e))?;
+
+        let pattern = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|p| RegexBuilder::new(p).case_insensitive(true).build())
+                .transpose()
+                .map_err(|e| format!("Invalid rule file {}: {}", source, e))
+        };
+        let (display_name, publisher) = (pattern(&file.display_name)?, pattern(&file.publisher)?);
+        if display_name.is_none() && publisher.is_none() {
+            return Err(format!("Invalid rule file {}: needs `display_name` or `publisher`", source));
+        }
+        Ok(AppRule { source: source.to_string(), display_name, publisher, file })
+    }
+
+    pub fn applies_to(&self, program: &ProgramInfo) -> bool {
+        self.display_name.as_ref().is_none_or(|re| re.is_match(&program.name))
+            && self.publisher.as_ref().is_none_or(|re| program.publisher.as_deref().is_some_and(|p| re.is_match(p)))
+    }
+
+    // Everything the rule lists that exists on this machine, with the user a registry key
+    // belongs to. Files and folders are attributed to their profile later.
+    pub fn items(
+        &self,
+        registry: &dyn RegistryBackend,
+        folders: &dyn KnownFolders,
+        user_profiles: &[UserProfile],
+        hives: &[UserHive],
+    ) -> Vec<(FoundItem, Option<String>)> {
+        let mut items = Vec::new();
+        for pattern in &self.file.paths {
+            for pattern in expand_path(pattern, folders, user_profiles) {
+                items.extend(glob_items(&pattern).into_iter().map(|item| (item, None)));
+            }
+        }
+        for key in &self.file.registry {
+            for (key, user) in expand_key(key, hives) {
+                if registry.open_key(&key).is_ok() {
+                    items.push((FoundItem::RegistryKey(key), user));
+                }
+            }
+        }
+        // A service is its key under `Services`; Windows drops it at the next restart.
+        for service in self.file.services.iter().filter(|s| !s.is_empty() && !s.contains('\\')) {
+            let key = registry::join(SERVICES, service);
+            if registry.open_key(&key).is_ok() {
+                items.push((FoundItem::RegistryKey(key), None));
+            }
+        }
+        for task in &self.file.scheduled_tasks {
+            items.extend(task_items(task, registry).into_iter().map(|item| (item, None)));
+        }
+        items
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<AppRule>,
+}
+
+impl RuleSet {
+    pub fn builtin() -> Result<Self, String> {
+        let rules = BUILTIN_RULES.iter().map(|(name, text)| AppRule::parse(name, text)).collect::<Result<_, _>>()?;
+        Ok(RuleSet { rules })
+    }
+
+    pub fn matching(&self, program: &ProgramInfo) -> Vec<&AppRule> {
+        self.rules.iter().filter(|rule| rule.applies_to(program)).collect()
+    }
+}
+
+// --- Rule Store ---
+
+// User-written rules, one file per program, next to the built-in ones.
+#[derive(Debug, Clone)]
+pub struct RuleStore {
+    dir: PathBuf,
+}
+
+impl RuleStore {
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        RuleStore { dir: dir.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|d| RuleStore::at(d.join("KuriUninstaller").join("rules")))
+    }
+
+    // The built-in rules plus every `.toml` and `.json` file in the folder.
+    pub fn load(&self) -> Result<RuleSet, String> {
+        let mut set = RuleSet::builtin()?;
+        let Ok(entries) = fs::read_dir(&self.dir) else { return Ok(set) };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml") || ext.eq_ignore_ascii_case("json")))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            set.rules.push(AppRule::parse(&path.display().to_string(), &text)?);
+        }
+        Ok(set)
+    }
+}
+
+// --- Resolving Rules ---
+
+// The pattern with its `%VAR%` references resolved: once per profile when it starts from a
+// per-user folder, otherwise once. Patterns with a variable that can't be resolved are dropped.
+fn expand_path(pattern: &str, folders: &dyn KnownFolders, user_profiles: &[UserProfile]) -> Vec<String> {
+    let upper = pattern.to_uppercase();
+    let per_user = USER_VARS.iter().any(|(var, _)| upper.contains(&format!("%{}%", var)));
+    let scopes: Vec<Option<&UserProfile>> = if per_user { user_profiles.iter().map(Some).collect() } else { vec![None] };
+
+    let mut patterns: Vec<String> = scopes
+        .into_iter()
+        .filter_map(|profile| {
+            let unresolved = Cell::new(false);
+            let expanded = profiles::expand_vars(pattern, |name| {
+                let value = variable(name, profile, folders);
+                unresolved.set(unresolved.get() || value.is_none());
+                value
+            });
+            (!unresolved.get()).then_some(expanded)
+        })
+        .collect();
+    patterns.dedup();
+    patterns
+}
+
+fn variable(name: &str, profile: Option<&UserProfile>, folders: &dyn KnownFolders) -> Option<String> {
+    if let Some(profile) = profile {
+        if let Some((_, below)) = USER_VARS.iter().find(|(var, _)| var.eq_ignore_ascii_case(name)) {
+            return Some(below.iter().fold(profile.path.clone(), |path, part| path.join(part)).to_string_lossy().into_owned());
+        }
+    }
+    match KnownFolder::from_variable(name) {
+        Some(folder) => folders.resolve(folder).map(|path| path.to_string_lossy().into_owned()),
+        None => std::env::var(name).ok().filter(|value| !value.is_empty()),
+    }
+}
+
+// The files and folders a glob matches. `dir\**` is the folder itself, deleted as one item.
+fn glob_items(pattern: &str) -> Vec<FoundItem> {
+    let pattern = pattern.strip_suffix(r"\**").or_else(|| pattern.strip_suffix("/**")).unwrap_or(pattern);
+    let options = glob::MatchOptions { case_sensitive: false, ..Default::default() };
+    let Ok(paths) = glob::glob_with(pattern, options) else { return vec![] };
+    paths
+        .filter_map(|path| path.ok())
+        .filter_map(|path| {
+            let meta = fs::symlink_metadata(&path).ok()?;
+            Some(if meta.is_dir() { FoundItem::Directory(path) } else { FoundItem::File(path) })
+        })
+        .collect()
+}
+
+// The key in every hive it applies to. A rule never names a whole hive.
+fn expand_key(key: &str, hives: &[UserHive]) -> Vec<(String, Option<String>)> {
+    let key = key.trim_matches('\\');
+    let Some((hive, rest)) = key.split_once('\\') else { return vec![] };
+    match hive.to_uppercase().as_str() {
+        "HKCU" | "HKEY_CURRENT_USER" => {
+            hives.iter().map(|hive| (registry::join(&hive.root, rest), Some(hive.user.clone()))).collect()
+        }
+        "HKLM" => vec![(registry::join("HKEY_LOCAL_MACHINE", rest), None)],
+        _ => vec![(key.to_string(), None)],
+    }
+}
+
+// A task, or a task folder with everything in it: its definition under `System32\Tasks`,
+// its key in the scheduler's `Tree`, and every index entry for the tasks' ids.
+fn task_items(task: &str, registry: &dyn RegistryBackend) -> Vec<FoundItem> {
+    let name = task.trim_matches(['\\', '/']);
+    let tree = registry::join(&registry::join(TASK_CACHE, "Tree"), name);
+    if name.is_empty() || registry.open_key(&tree).is_err() {
+        return vec![];
+    }
+
+    let mut items = Vec::new();
+    if let Some(root) = std::env::var_os("SystemRoot") {
+        let definition = PathBuf::from(root).join("System32").join("Tasks").join(name);
+        match fs::symlink_metadata(&definition) {
+            Ok(meta) if meta.is_dir() => items.push(FoundItem::Directory(definition)),
+            Ok(_) => items.push(FoundItem::File(definition)),
+            Err(_) => {}
+        }
+    }
+    for id in task_ids(registry, &tree) {
+        for index in TASK_INDEXES {
+            let key = registry::join(&registry::join(TASK_CACHE, index), &id);
+            if registry.open_key(&key).is_ok() {
+                items.push(FoundItem::RegistryKey(key));
+            }
+        }
+    }
+    items.push(FoundItem::RegistryKey(tree));
+    items
+}
+
+fn task_ids(registry: &dyn RegistryBackend, key: &str) -> Vec<String> {
+    let mut ids: Vec<String> = registry.get_string(key, "Id").into_iter().collect();
+    for subkey in registry.enum_keys(key).unwrap_or_default() {
+        ids.extend(task_ids(registry, &registry::join(key, &subkey)));
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::known_folders::EnvKnownFolders;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    fn program(name: &str, publisher: Option<&str>) -> ProgramInfo {
+        ProgramInfo { name: name.into(), publisher: publisher.map(str::to_string), ..Default::default() }
+    }
+
+    #[test]
+    fn parses_and_matches_rules() {
+        let set = RuleSet::builtin().unwrap();
+        assert_eq!(set.matching(&program("Google Chrome", Some("Google LLC"))).len(), 1);
+        assert!(set.matching(&program("Google Chrome Beta", Some("Google LLC"))).is_empty());
+        assert!(set.matching(&program("Google Chrome", Some("Contoso"))).is_empty());
+
+        let json = AppRule::parse("contoso.json", r#"{ "publisher": "^contoso", "services": ["ContosoSvc"] }"#).unwrap();
+        assert!(json.applies_to(&program("Anything", Some("Contoso, Inc."))));
+        assert!(!json.applies_to(&program("Anything", None)));
+
+        assert!(AppRule::parse("empty.toml", "paths = []").unwrap_err().contains("display_name"));
+        assert!(AppRule::parse("typo.toml", "display_nam = 'x'").is_err());
+        assert!(AppRule::parse("regex.toml", "display_name = '('").is_err());
+    }
+
+    #[test]
+    fn resolves_paths_keys_services_and_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (alice, program_data) = (dir.path().join("Users").join("alice"), dir.path().join("ProgramData"));
+        let local = alice.join("AppData").join("Local");
+        fs::create_dir_all(local.join("Contoso").join("Editor").join("Cache")).unwrap();
+        fs::create_dir_all(program_data.join("Contoso")).unwrap();
+        fs::write(program_data.join("Contoso").join("editor.lic"), "").unwrap();
+        fs::write(program_data.join("Contoso").join("other.lic"), "").unwrap();
+
+        let reg = MemoryRegistry::new();
+        reg.create_key(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso\Editor").unwrap();
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services\ContosoSvc").unwrap();
+        let tree = format!(r"{}\Tree\Contoso\Updater", TASK_CACHE);
+        reg.set_value(&tree, "Id", &RegValue::String("{42}".into())).unwrap();
+        reg.create_key(&format!(r"{}\Tasks\{{42}}", TASK_CACHE)).unwrap();
+        reg.create_key(&format!(r"{}\Logon\{{42}}", TASK_CACHE)).unwrap();
+
+        let rule = AppRule::parse(
+            "contoso.toml",
+            r#"
+                display_name = 'Contoso Editor'
+                paths = ['%LOCALAPPDATA%/Contoso/Editor/**', '%ProgramData%/Contoso/editor.*', '%MISSING%/Contoso']
+                registry = ['HKCU\Software\Contoso\Editor', 'HKLM\SOFTWARE\Contoso\Editor']
+                services = ['ContosoSvc', 'MissingSvc']
+                scheduled_tasks = ['\Contoso\Updater']
+            "#,
+        )
+        .unwrap();
+        let profile = UserProfile { sid: "S-1-5-21-1-1001".into(), name: "alice".into(), path: alice };
+        let hives = [UserHive { user: "alice".into(), root: r"HKEY_USERS\S-1-5-21-1-1001".into() }];
+        let folders = EnvKnownFolders::from_vars([("ProgramData", program_data.to_string_lossy())]);
+
+        let items = rule.items(&reg, &folders, &[profile], &hives);
+        assert_eq!(
+            items,
+            [
+                (FoundItem::Directory(local.join("Contoso").join("Editor")), None),
+                (FoundItem::File(program_data.join("Contoso").join("editor.lic")), None),
+                (FoundItem::RegistryKey(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso\Editor".into()), Some("alice".into())),
+                (FoundItem::RegistryKey(r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services\ContosoSvc".into()), None),
+                (FoundItem::RegistryKey(format!(r"{}\Tasks\{{42}}", TASK_CACHE)), None),
+                (FoundItem::RegistryKey(format!(r"{}\Logon\{{42}}", TASK_CACHE)), None),
+                (FoundItem::RegistryKey(tree), None),
+            ]
+        );
+    }
+}
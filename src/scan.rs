@@ -0,0 +1,1503 @@
+use crate::config::Exclusions;
+use crate::known_folders::{KnownFolder, KnownFolders};
+use crate::metadata::{self, ItemMetadata, KeyMetadata};
+use crate::profiles::{self, MountedHives, UserHive};
+use crate::protected::{self, Protection};
+use crate::registry::{self, RegValue, RegistryBackend};
+use crate::rules::{AppRule, RuleSet};
+use crate::trace::{InstallManifest, ManifestStore};
+use crate::{FoundItem, ProgramInfo};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedSender;
+
+// Legal-entity suffixes stripped from `Publisher` to get the folder name vendors actually use.
+const PUBLISHER_SUFFIXES: [&str; 7] = ["inc", "corporation", "corp", "llc", "ltd", "gmbh", "co"];
+
+// Terms shorter than this ("Go", "R") only count when they are the whole name.
+const MIN_SUBSTRING_TERM_LEN: usize = 3;
+// Words in display names that say nothing about which program it is.
+const STOP_WORDS: [&str; 13] =
+    ["a", "an", "and", "by", "for", "of", "the", "with", "update", "updates", "version", "edition", "build"];
+// Architecture markers, e.g. "Python 3.12 (64-bit)" or "7-Zip x64"; always dropped.
+const ARCHITECTURES: [&str; 11] =
+    ["x64", "x86", "x86_64", "amd64", "arm64", "win32", "win64", "32-bit", "64-bit", "32bit", "64bit"];
+// Executables found in every other vendor's folder, e.g. an icon taken from `unins000.exe`.
+const GENERIC_EXECUTABLES: [&str; 9] =
+    ["setup", "install", "installer", "uninstall", "uninstaller", "uninst", "update", "updater", "launcher"];
+// Items at or above this confidence are checked by default.
+pub const PRESELECT_CONFIDENCE: u8 = 70;
+
+// --- Scan Results ---
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    RecordedAtInstall,
+    ListedInRule,
+    InsideInstallLocation,
+    ExactName,
+    UnderPublisherFolder,
+    ReferencesInstallLocation,
+    NamePrefix,
+    NameWord,
+    Substring,
+}
+
+impl MatchReason {
+    pub fn confidence(self) -> u8 {
+        match self {
+            MatchReason::RecordedAtInstall => 100,
+            MatchReason::ListedInRule => 98,
+            MatchReason::InsideInstallLocation => 95,
+            MatchReason::ExactName => 90,
+            MatchReason::UnderPublisherFolder => 85,
+            // Below PRESELECT_CONFIDENCE: keys such as `Run` and `SharedDLLs` are shared, and a
+            // folder two programs live in says little about which one a value belongs to.
+            MatchReason::ReferencesInstallLocation => 65,
+            MatchReason::NamePrefix => 60,
+            MatchReason::NameWord => 50,
+            MatchReason::Substring => 30,
+        }
+    }
+}
+
+impl std::fmt::Display for MatchReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MatchReason::RecordedAtInstall => "created by the traced install",
+            MatchReason::ListedInRule => "listed in the program's rule",
+            MatchReason::InsideInstallLocation => "inside install location",
+            MatchReason::ExactName => "exact name match",
+            MatchReason::UnderPublisherFolder => "under the publisher's folder",
+            MatchReason::ReferencesInstallLocation => "refers to the install location",
+            MatchReason::NamePrefix => "name starts with program name",
+            MatchReason::NameWord => "matches one word of the program name",
+            MatchReason::Substring => "substring match only",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Leftover {
+    #[serde(flatten)]
+    pub item: FoundItem,
+    pub confidence: u8,
+    pub reason: MatchReason,
+    // The account whose profile the item belongs to; `None` for machine-wide items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    // Size, times and owner of a file or folder; a matched folder's contents aren't
+    // reported separately, so its size covers everything inside it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ItemMetadata>,
+    // Subtree counts, last-write time and a preview of a registry key's values.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_metadata: Option<KeyMetadata>,
+    // The data of a registry value item, formatted for display.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_data: Option<String>,
+    // Another installed program that also claims the item, e.g. a vendor folder two
+    // products share. Such items are never checked by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shared_with: Option<String>,
+}
+
+impl Leftover {
+    fn new(item: FoundItem, reason: MatchReason) -> Self {
+        let metadata = match &item {
+            FoundItem::File(path) | FoundItem::Directory(path) => ItemMetadata::read(path),
+            FoundItem::RegistryKey(_) | FoundItem::RegistryValue { .. } => None,
+        };
+        Leftover {
+            item,
+            confidence: reason.confidence(),
+            reason,
+            user: None,
+            metadata,
+            key_metadata: None,
+            value_data: None,
+            shared_with: None,
+        }
+    }
+
+    fn with_key_metadata(mut self, registry: &dyn RegistryBackend) -> Self {
+        match &self.item {
+            FoundItem::RegistryKey(key) => self.key_metadata = KeyMetadata::read(registry, key),
+            FoundItem::RegistryValue { key, name } => {
+                self.value_data = registry.get_value(key, name).ok().map(|value| metadata::format_value(&value));
+            }
+            _ => {}
+        }
+        self
+    }
+
+    fn for_user(mut self, user: Option<&str>) -> Self {
+        self.user = user.map(str::to_string);
+        self
+    }
+
+    pub fn is_preselected(&self) -> bool {
+        self.confidence >= PRESELECT_CONFIDENCE && self.shared_with.is_none()
+    }
+}
+
+// How far below a search root the name scan looks; vendor folders sit near the top.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+// How far below each `Software` key the registry search looks, e.g. down to
+// `Classes\CLSID\{GUID}\InprocServer32`.
+pub const REGISTRY_MAX_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    // Temporarily mounts signed-out users' NTUSER.DAT so their registry is scanned too.
+    pub include_signed_out_users: bool,
+    // Folder holding user profiles, for when `ProfileList` is missing or incomplete.
+    pub profiles_root: Option<PathBuf>,
+    pub max_depth: usize,
+    // Names may match anywhere inside a word instead of only on word boundaries.
+    pub aggressive: bool,
+    // Extra paths and registry keys that are never offered for deletion, on top of the built-in list.
+    pub protected: Vec<String>,
+    // Searched on top of the known folders and every profile's AppData.
+    pub extra_roots: Vec<PathBuf>,
+    // Path globs and case-insensitive registry key regexes for items never reported.
+    pub exclude_paths: Vec<String>,
+    pub exclude_keys: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            include_signed_out_users: false,
+            profiles_root: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            aggressive: false,
+            protected: Vec::new(),
+            extra_roots: Vec::new(),
+            exclude_paths: Vec::new(),
+            exclude_keys: Vec::new(),
+        }
+    }
+}
+
+// A `Software` key to search, and the user it belongs to (`None` for HKLM).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoftwareRoot {
+    pub path: String,
+    pub user: Option<String>,
+}
+
+pub fn software_roots(hives: &[UserHive]) -> Vec<SoftwareRoot> {
+    let machine = [r"HKEY_LOCAL_MACHINE\SOFTWARE", r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node"]
+        .into_iter()
+        .map(|path| SoftwareRoot { path: path.to_string(), user: None });
+    let users = hives
+        .iter()
+        .map(|hive| SoftwareRoot { path: registry::join(&hive.root, "Software"), user: Some(hive.user.clone()) });
+    machine.chain(users).collect()
+}
+
+// --- Search Terms ---
+
+// What a program's files and keys may be named after. Whole names match exactly, by
+// prefix or as a substring; a single word of a longer name ("firefox" from "Mozilla
+// Firefox") only matches exactly, and weighs less.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchTerms {
+    pub names: Vec<String>,
+    pub words: Vec<String>,
+    // Lets names match anywhere inside a word, so "git" also finds "digital"; by default
+    // a prefix or substring match has to start and end on a word boundary.
+    pub aggressive: bool,
+}
+
+pub fn generate_search_terms(program: &ProgramInfo) -> SearchTerms {
+    let words = name_words(&program.name);
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    let keywords: Vec<&String> = lower.iter().filter(|w| !STOP_WORDS.contains(&w.as_str())).collect();
+
+    let mut names = vec![program.name.to_lowercase(), lower.join(" "), lower.concat()];
+    names.push(keywords.iter().map(|w| w.as_str()).collect::<Vec<_>>().join(" "));
+    names.push(keywords.iter().map(|w| w.as_str()).collect());
+
+    if let Some(location) = &program.install_location {
+        if let Some(folder_name) = location.file_name() {
+            names.push(folder_name.to_string_lossy().to_lowercase());
+        }
+    }
+
+    // The Uninstall key name is either a readable name or a product code such as
+    // `{GUID}`, which MSI caches and per-product registry keys are named after.
+    if let Some((_, key_name)) = program.registry_key.rsplit_once('\\') {
+        names.push(key_name.to_lowercase());
+    }
+
+    // The main executable's name, e.g. `studio` from `C:\Contoso\studio.exe,0`.
+    if let Some(icon) = program.display_icon.as_deref().and_then(executable_path) {
+        let file_name = icon.rsplit(['\\', '/']).next().unwrap_or(&icon).to_lowercase();
+        if let Some(stem) = file_name.strip_suffix(".exe").filter(|stem| !is_generic_executable(stem)) {
+            names.push(stem.to_string());
+        }
+    }
+
+    names.retain(|t| !t.is_empty());
+    names.sort();
+    names.dedup();
+
+    // The publisher's own name would match every product it makes.
+    let publisher = publisher_folder(program).map(|p| p.to_lowercase()).unwrap_or_default();
+    let mut single: Vec<String> = if keywords.len() > 1 { keywords.into_iter().cloned().collect() } else { Vec::new() };
+    single.retain(|w| w.len() >= MIN_SUBSTRING_TERM_LEN && !publisher.split(' ').any(|p| p == w) && !names.contains(w));
+    single.sort();
+    single.dedup();
+
+    SearchTerms { names, words: single, aggressive: false }
+}
+
+// The words of a display name without what only tells installs apart: bracketed notes,
+// trademark signs, versions, architectures and locales, e.g. "Mozilla Firefox (x64 en-US)"
+// -> ["Mozilla", "Firefox"]. Numbers are kept unless they trail the name ("The Witcher 3:
+// Wild Hunt", but "Visual Studio 2022"). A name that is nothing but these is kept whole.
+pub fn name_words(name: &str) -> Vec<String> {
+    let mut plain = String::new();
+    let mut depth = 0usize;
+    for c in name.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            '\u{2122}' | '\u{00AE}' | '\u{00A9}' => {}
+            _ if depth == 0 => plain.push(c),
+            _ => {}
+        }
+    }
+
+    let mut words: Vec<String> = plain
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '+' && c != '#'))
+        .filter(|w| !w.is_empty())
+        .filter(|w| {
+            let lower = w.to_lowercase();
+            !is_version(&lower) && !is_locale(&lower) && !ARCHITECTURES.contains(&lower.as_str())
+        })
+        .map(str::to_string)
+        .collect();
+    while words.len() > 1 && words.last().is_some_and(|w| is_number(w)) {
+        words.pop();
+    }
+    if words.is_empty() {
+        return name.split_whitespace().map(str::to_string).collect();
+    }
+    words
+}
+
+// "1.2", "23.01.0", "v2", "v1.4".
+fn is_version(word: &str) -> bool {
+    let (digits, prefixed) = word.strip_prefix('v').map_or((word, false), |rest| (rest, true));
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && (prefixed || digits.contains('.'))
+}
+
+// "2022", "2015-2022".
+fn is_number(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_digit()) && word.chars().all(|c| c.is_ascii_digit() || c == '-')
+}
+
+// "setup", "uninstall", "unins000".
+fn is_generic_executable(stem: &str) -> bool {
+    GENERIC_EXECUTABLES.contains(&stem) || stem.strip_prefix("unins").is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+}
+
+// "en-us", "zh-cn".
+fn is_locale(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    bytes.len() == 5 && bytes[2] == b'-' && word.chars().filter(|c| *c != '-').all(|c| c.is_ascii_alphabetic())
+}
+
+// `Publisher` with legal suffixes removed, e.g. "Contoso, Inc." -> "Contoso".
+pub fn publisher_folder(program: &ProgramInfo) -> Option<String> {
+    let mut publisher = program.publisher.as_deref()?.trim().trim_end_matches([',', ' ', '.']).to_string();
+    while let Some(suffix) = PUBLISHER_SUFFIXES.iter().find(|suffix| {
+        let lower = publisher.to_lowercase();
+        lower == **suffix || lower.ends_with(&format!(" {}", suffix))
+    }) {
+        publisher.truncate(publisher.len() - suffix.len());
+        publisher = publisher.trim_end_matches([',', ' ', '.']).to_string();
+    }
+    Some(publisher).filter(|p| !p.is_empty())
+}
+
+// Product folder names to try under the publisher: the display name, and the display
+// name without the publisher prefix ("Contoso Studio" -> "Studio").
+fn product_folders(program: &ProgramInfo, publisher: &str) -> Vec<String> {
+    let mut names = vec![program.name.clone(), name_words(&program.name).join(" ")];
+    for i in 0..names.len() {
+        if let Some(rest) = names[i].strip_prefix(publisher) {
+            let rest = rest.trim();
+            if !rest.is_empty() {
+                names.push(rest.to_string());
+            }
+        }
+    }
+    names.dedup();
+    names
+}
+
+// Extracts the executable from a command line or icon reference such as
+// `"C:\Program Files\App\app.exe" /S` or `C:\App\app.exe,0`.
+pub fn executable_path(command: &str) -> Option<String> {
+    let command = command.trim();
+    let path = if let Some(rest) = command.strip_prefix('"') {
+        rest.split('"').next()?
+    } else {
+        // ASCII lowercasing keeps byte offsets, so `end` also indexes `command`.
+        let end = command.to_ascii_lowercase().find(".exe").map_or(command.len(), |i| i + 4);
+        &command[..end]
+    };
+    let path = path.split(',').next()?.trim();
+    Some(path.to_string()).filter(|p| !p.is_empty())
+}
+
+fn parent_dir(path: &str) -> Option<PathBuf> {
+    path.rfind(['\\', '/']).map(|i| PathBuf::from(&path[..i])).filter(|p| !p.as_os_str().is_empty())
+}
+
+// Folders the program's own metadata points at besides `InstallLocation`: where its icon
+// and uninstaller live, and the installer cache when it is named after the product code.
+pub fn metadata_dirs(program: &ProgramInfo) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = [&program.display_icon, &program.uninstall_string]
+        .into_iter()
+        .flatten()
+        .filter_map(|command| executable_path(command))
+        // Shared system tools such as MsiExec.exe and rundll32.exe say nothing about the program.
+        .filter(|exe| !exe.to_lowercase().contains("\\windows\\"))
+        .filter_map(|exe| parent_dir(&exe))
+        .collect();
+
+    let key_name = program.registry_key.rsplit('\\').next().unwrap_or_default();
+    if let Some(source) = &program.install_source {
+        let source_name = source.to_string_lossy();
+        let source_name = source_name.trim_end_matches(['\\', '/']).rsplit(['\\', '/']).next().unwrap_or_default();
+        if !key_name.is_empty() && source_name.eq_ignore_ascii_case(key_name) {
+            dirs.push(source.clone());
+        }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+// Picks the strongest reason `name` matches any term, if it matches at all.
+fn match_name(name: &str, search_terms: &SearchTerms) -> Option<MatchReason> {
+    let lower = name.to_lowercase();
+    let stem = lower.rsplit_once('.').map_or(lower.as_str(), |(stem, _)| stem);
+    let word = search_terms.words.iter().any(|w| lower == *w || stem == w).then_some(MatchReason::NameWord);
+    let tokens = name_tokens(name);
+    let stem_tokens = name.rsplit_once('.').map_or(tokens.len(), |(stem, _)| name_tokens(stem).len());
+    search_terms
+        .names
+        .iter()
+        .filter_map(|term| {
+            if lower == *term || stem == term {
+                Some(MatchReason::ExactName)
+            } else if term.len() < MIN_SUBSTRING_TERM_LEN {
+                None
+            } else if search_terms.aggressive {
+                if lower.starts_with(term.as_str()) {
+                    Some(MatchReason::NamePrefix)
+                } else if lower.contains(term.as_str()) {
+                    Some(MatchReason::Substring)
+                } else {
+                    None
+                }
+            } else {
+                token_runs(&tokens, &name_tokens(term).concat())
+                    .map(|(start, end)| match start {
+                        0 if end == tokens.len() || end == stem_tokens => MatchReason::ExactName,
+                        0 => MatchReason::NamePrefix,
+                        _ => MatchReason::Substring,
+                    })
+                    .max_by_key(|reason| reason.confidence())
+            }
+        })
+        .chain(word)
+        .max_by_key(|reason| reason.confidence())
+}
+
+// Lowercased words of a file or key name, split at separators and CamelCase transitions:
+// "ContosoStudio_v2.log" -> ["contoso", "studio", "v2", "log"], "HTTPServer" -> ["http", "server"].
+fn name_tokens(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() && c != '+' && c != '#' {
+            tokens.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|j| chars[j]);
+        let next = chars.get(i + 1);
+        let camel = c.is_uppercase()
+            && previous.is_some_and(|p| p.is_lowercase() || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase())));
+        if camel && !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        current.extend(c.to_lowercase());
+    }
+    tokens.extend((!current.is_empty()).then_some(current));
+    tokens
+}
+
+// Every run of consecutive tokens that spells `term` (with its own separators removed), as
+// token ranges.
+fn token_runs<'a>(tokens: &'a [String], term: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    (0..tokens.len()).filter_map(move |start| {
+        let mut joined = String::new();
+        for (end, token) in tokens.iter().enumerate().skip(start) {
+            joined.push_str(token);
+            if joined == term {
+                return Some((start, end + 1));
+            }
+            if joined.len() >= term.len() {
+                return None;
+            }
+        }
+        None
+    })
+}
+
+// --- Progress and Cancellation ---
+
+// Progress is reported at least this often, counted in entries visited.
+const PROGRESS_INTERVAL: u64 = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanEvent {
+    Progress(ScanProgress),
+    // Everything one root turned up, as soon as that root is done. The final result
+    // still arrives as a whole, deduplicated and sorted.
+    Found(Vec<Leftover>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanProgress {
+    // The folder (or "Registry") most recently started.
+    pub current: String,
+    pub visited: u64,
+    pub matches: usize,
+}
+
+// Shared by a running scan and whoever started it: progress and partial results go out to
+// the listener, and every walker stops at its next entry once the scan is cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct ScanControl {
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<Mutex<ScanProgress>>,
+    listener: Option<UnboundedSender<ScanEvent>>,
+}
+
+impl ScanControl {
+    pub fn reporting_to(&self, listener: UnboundedSender<ScanEvent>) -> Self {
+        ScanControl { listener: Some(listener), ..self.clone() }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn entering(&self, current: String) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.current = current;
+        self.report(&progress);
+    }
+
+    fn visited(&self, matched: bool) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.visited += 1;
+        if matched {
+            progress.matches += 1;
+        }
+        if matched || progress.visited.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report(&progress);
+        }
+    }
+
+    fn report(&self, progress: &ScanProgress) {
+        self.send(ScanEvent::Progress(progress.clone()));
+    }
+
+    fn found(&self, leftovers: &[Leftover]) {
+        if !leftovers.is_empty() {
+            self.send(ScanEvent::Found(leftovers.to_vec()));
+        }
+    }
+
+    fn send(&self, event: ScanEvent) {
+        if let Some(listener) = &self.listener {
+            let _ = listener.send(event);
+        }
+    }
+}
+
+// --- Scanning ---
+
+pub async fn scan_for_leftovers(
+    program: ProgramInfo,
+    registry: Arc<dyn RegistryBackend>,
+    manifests: ManifestStore,
+    folders: Arc<dyn KnownFolders>,
+    rules: Arc<RuleSet>,
+    options: ScanOptions,
+    control: ScanControl,
+) -> Result<Vec<Leftover>, String> {
+    // A manifest recorded while tracing the install is authoritative; name matching only guesses.
+    let mut protected = protected::system_protection(registry.as_ref(), folders.as_ref(), &options);
+    let exclusions = Exclusions::new(&options.exclude_paths, &options.exclude_keys)?;
+    if let Some(manifest) = manifests.load(&program.registry_key)? {
+        let mut results = manifest_leftovers(&manifest, registry.as_ref());
+        results.retain(|l| !protected.is_protected(&l.item) && !exclusions.excludes(&l.item));
+        return Ok(results);
+    }
+
+    let search_terms = SearchTerms { aggressive: options.aggressive, ..generate_search_terms(&program) };
+    let publisher = publisher_folder(&program);
+    // Every user's AppData, not only that of the administrator running the scan.
+    let user_profiles = profiles::user_profiles(registry.as_ref(), options.profiles_root.as_deref());
+    let mut search_dirs = folders.existing();
+    search_dirs.extend(user_profiles.iter().flat_map(profiles::app_data_dirs));
+    search_dirs.extend(options.extra_roots.iter().filter(|dir| dir.is_dir()).cloned());
+    search_dirs.sort();
+    search_dirs.dedup();
+    // A root is never a leftover itself, however well its name matches. The program's own
+    // install folders are searched too, but those may go.
+    for dir in &search_dirs {
+        protected.protect_path(dir, Protection::Itself);
+    }
+
+    let mut install_dirs: Vec<PathBuf> = program.install_location.iter().cloned().collect();
+    install_dirs.extend(metadata_dirs(&program));
+    // An icon or uninstaller sitting directly in `Program Files` or `ProgramData` would make
+    // everything there count as inside the install folder. A folder that is itself protected
+    // or searched anyway is too broad to go by.
+    install_dirs.retain(|dir| {
+        !dir.as_os_str().is_empty()
+            && !search_dirs.contains(dir)
+            && !protected.is_protected(&FoundItem::Directory(dir.to_path_buf()))
+    });
+    // Registry values may still point into the install folders after they are gone.
+    let install_paths: Vec<String> = install_dirs.iter().map(|dir| install_path(dir)).collect();
+    install_dirs.retain(|dir| dir.exists());
+    search_dirs.extend(install_dirs.iter().cloned());
+
+    // Vendors keep per-product folders under these, e.g. ProgramData\Contoso\Editor.
+    let mut app_data_roots: Vec<PathBuf> = [KnownFolder::RoamingAppData, KnownFolder::LocalAppData, KnownFolder::ProgramData]
+        .into_iter()
+        .filter_map(|folder| folders.resolve(folder))
+        .collect();
+    app_data_roots.extend(user_profiles.iter().flat_map(profiles::app_data_dirs));
+    app_data_roots.sort();
+    app_data_roots.dedup();
+
+    // Kept alive until the registry has been searched, then unloaded again.
+    let mounted = if options.include_signed_out_users {
+        MountedHives::signed_out_users(registry.clone())
+    } else {
+        MountedHives::none(registry.clone())
+    };
+    let hives = profiles::user_hives(registry.as_ref(), &mounted);
+    let software = software_roots(&hives);
+    let rules: Vec<AppRule> = rules.matching(&program).into_iter().cloned().collect();
+
+    let mut searches = tokio::task::JoinSet::new();
+    if !rules.is_empty() {
+        // A rule written for the program knows better than name matching.
+        let (registry, folders, user_profiles, control) =
+            (registry.clone(), folders.clone(), user_profiles.clone(), control.clone());
+        searches.spawn_blocking(move || {
+            let mut results = Vec::new();
+            for rule in &rules {
+                control.entering(rule.source.clone());
+                results.extend(rule.items(registry.as_ref(), folders.as_ref(), &user_profiles, &hives).into_iter().map(
+                    |(item, user)| {
+                        Leftover::new(item, MatchReason::ListedInRule)
+                            .for_user(user.as_deref())
+                            .with_key_metadata(registry.as_ref())
+                    },
+                ));
+            }
+            results
+        });
+    } else {
+        // Every root is walked on its own blocking thread; most of the time goes to waiting on the disk.
+        let search_terms = Arc::new(search_terms);
+        let install_dirs = Arc::new(install_dirs);
+        let max_depth = options.max_depth;
+        for dir in search_dirs {
+            let (terms, install_dirs, publisher, control) =
+                (search_terms.clone(), install_dirs.clone(), publisher.clone(), control.clone());
+            searches.spawn_blocking(move || {
+                scan_filesystem(&[dir], &terms, &install_dirs, publisher.as_deref(), max_depth, &control)
+            });
+        }
+        let (registry, control) = (registry.clone(), control.clone());
+        searches.spawn_blocking(move || {
+            control.entering("Registry".to_string());
+            let mut results = scan_registry(registry.as_ref(), &software, &search_terms, &install_paths, &control);
+            results.extend(scan_publisher_paths(&program, &app_data_roots, &software, registry.as_ref()));
+            results
+        });
+    }
+
+    // Collected in the order the searches finish, so quick roots show up first.
+    let mut results = Vec::new();
+    while let Some(found) = searches.join_next().await {
+        let mut found = found.map_err(|e| e.to_string())?;
+        found.retain(|l| !protected.is_protected(&l.item) && !exclusions.excludes(&l.item));
+        attribute_to_profiles(&mut found, &user_profiles);
+        control.found(&found);
+        results.extend(found);
+    }
+    drop(mounted);
+    if control.is_cancelled() {
+        return Err("Scan cancelled".to_string());
+    }
+
+    // The same item can be reached from several roots; keep its strongest match.
+    results.sort_by(|a, b| a.item.to_string().cmp(&b.item.to_string()).then(b.confidence.cmp(&a.confidence)));
+    results.dedup_by(|a, b| a.item == b.item);
+    results.sort_by(|a, b| b.confidence.cmp(&a.confidence).then(a.item.to_string().cmp(&b.item.to_string())));
+
+    Ok(results)
+}
+
+// The space deleting these would free, not counting anything inside a listed folder twice.
+pub fn reclaimable_bytes<'a>(leftovers: impl Iterator<Item = &'a Leftover> + Clone) -> u64 {
+    let folders: Vec<&PathBuf> = leftovers
+        .clone()
+        .filter_map(|l| match &l.item {
+            FoundItem::Directory(path) => Some(path),
+            _ => None,
+        })
+        .collect();
+    leftovers
+        .filter(|l| match &l.item {
+            FoundItem::File(path) | FoundItem::Directory(path) => {
+                !folders.iter().any(|folder| path != *folder && path.starts_with(folder))
+            }
+            FoundItem::RegistryKey(_) | FoundItem::RegistryValue { .. } => false,
+        })
+        .filter_map(|l| l.metadata.as_ref().map(|m| m.bytes))
+        .sum()
+}
+
+// Flags items another installed program claims: ones inside or above its install folder
+// or Uninstall key, and ones named after it at least as closely as after `program`.
+pub fn mark_shared(results: &mut [Leftover], program: &ProgramInfo, installed: &[ProgramInfo]) {
+    let others: Vec<(&ProgramInfo, SearchTerms)> = installed
+        .iter()
+        .filter(|other| other.registry_key != program.registry_key)
+        .map(|other| (other, generate_search_terms(other)))
+        .collect();
+    for leftover in results.iter_mut().filter(|l| l.shared_with.is_none()) {
+        leftover.shared_with =
+            others.iter().find(|(other, terms)| claims(other, terms, leftover)).map(|(other, _)| other.name.clone());
+    }
+}
+
+fn claims(other: &ProgramInfo, terms: &SearchTerms, leftover: &Leftover) -> bool {
+    let name = match &leftover.item {
+        FoundItem::File(path) | FoundItem::Directory(path) => {
+            let location = other.install_location.as_ref().filter(|l| !l.as_os_str().is_empty());
+            if location.is_some_and(|l| path.starts_with(l) || l.starts_with(path)) {
+                return true;
+            }
+            path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+        }
+        FoundItem::RegistryKey(key) => {
+            let (lower, other_key) = (key.to_lowercase(), other.registry_key.to_lowercase());
+            let within = |inner: &str, outer: &str| inner == outer || inner.starts_with(&format!("{}\\", outer));
+            if within(&lower, &other_key) || within(&other_key, &lower) {
+                return true;
+            }
+            key.rsplit('\\').next().unwrap_or_default().to_string()
+        }
+        FoundItem::RegistryValue { key, name } => {
+            let (lower, other_key) = (key.to_lowercase(), other.registry_key.to_lowercase());
+            if lower == other_key || lower.starts_with(&format!("{}\\", other_key)) {
+                return true;
+            }
+            let location = other.install_location.as_deref().map(install_path).filter(|l| !l.is_empty());
+            let text = format!("{}\n{}", name, leftover.value_data.as_deref().unwrap_or_default());
+            return location.is_some_and(|l| mentions_any(&text, &[l]));
+        }
+    };
+    match_name(&name, terms).is_some_and(|reason| reason.confidence() >= leftover.confidence)
+}
+
+// Files and folders inside someone's profile belong to that user.
+pub fn attribute_to_profiles(results: &mut [Leftover], user_profiles: &[profiles::UserProfile]) {
+    for leftover in results.iter_mut().filter(|l| l.user.is_none()) {
+        if let FoundItem::File(path) | FoundItem::Directory(path) = &leftover.item {
+            leftover.user = profiles::owner(user_profiles, path).map(|p| p.name.clone());
+        }
+    }
+}
+
+// The manifest's items that still exist.
+pub fn manifest_leftovers(manifest: &InstallManifest, registry: &dyn RegistryBackend) -> Vec<Leftover> {
+    manifest
+        .items()
+        .into_iter()
+        .filter(|item| match item {
+            FoundItem::File(path) | FoundItem::Directory(path) => path.exists(),
+            FoundItem::RegistryKey(key) => registry.open_key(key).is_ok(),
+            FoundItem::RegistryValue { key, name } => registry.get_value(key, name).is_ok(),
+        })
+        .map(|item| Leftover::new(item, MatchReason::RecordedAtInstall).with_key_metadata(registry))
+        .collect()
+}
+
+pub fn scan_filesystem(
+    search_dirs: &[PathBuf],
+    search_terms: &SearchTerms,
+    install_dirs: &[PathBuf],
+    publisher: Option<&str>,
+    max_depth: usize,
+    control: &ScanControl,
+) -> Vec<Leftover> {
+    let publisher = publisher.map(str::to_lowercase);
+    let mut results = Vec::new();
+    for dir in search_dirs {
+        control.entering(dir.display().to_string());
+        let mut walker = walkdir::WalkDir::new(dir)
+            .max_depth(max_depth)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_link(e));
+        while let Some(entry) = walker.next() {
+            if control.is_cancelled() {
+                return results;
+            }
+            let Ok(entry) = entry else { continue };
+            let entry_name = entry.file_name().to_string_lossy();
+            // Other roots are searched through even when their own name matches, since they
+            // are never leftovers themselves; only an install folder can be one.
+            let matched = if entry.depth() == 0 && !install_dirs.contains(dir) {
+                None
+            } else {
+                match_name(&entry_name, search_terms)
+            };
+            control.visited(matched.is_some());
+            if let Some(reason) = matched {
+                let parent_name = entry.path().parent().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_lowercase());
+                let reason = if install_dirs.iter().any(|d| entry.path().starts_with(d)) {
+                    MatchReason::InsideInstallLocation
+                } else if publisher.is_some() && parent_name == publisher && reason != MatchReason::ExactName {
+                    MatchReason::UnderPublisherFolder
+                } else {
+                    reason
+                };
+                let leftover = if entry.file_type().is_dir() {
+                    // Deleting the folder takes its contents with it, so they aren't listed.
+                    walker.skip_current_dir();
+                    Leftover::new(FoundItem::Directory(entry.into_path()), reason)
+                } else {
+                    Leftover::new(FoundItem::File(entry.into_path()), reason)
+                };
+                results.push(leftover);
+            }
+        }
+    }
+    results
+}
+
+// Symlinks, junctions and other reparse points are never followed: they can loop back up
+// the tree or lead into folders that belong to something else.
+pub fn is_link(entry: &walkdir::DirEntry) -> bool {
+    if entry.path_is_symlink() {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+        if let Ok(meta) = entry.metadata() {
+            return meta.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0;
+        }
+    }
+    false
+}
+
+// Looks directly for `<root>\<Publisher>\<Product>` folders and `Software\<Publisher>\<Product>`
+// keys, which the name-based walk only finds when the publisher itself matches a term.
+pub fn scan_publisher_paths(
+    program: &ProgramInfo,
+    app_data_roots: &[PathBuf],
+    software_roots: &[SoftwareRoot],
+    registry: &dyn RegistryBackend,
+) -> Vec<Leftover> {
+    let Some(publisher) = publisher_folder(program) else { return vec![] };
+    let products = product_folders(program, &publisher);
+    let mut results = Vec::new();
+
+    for root in app_data_roots {
+        for product in &products {
+            let path = root.join(&publisher).join(product);
+            if path.is_dir() {
+                results.push(Leftover::new(FoundItem::Directory(path), MatchReason::UnderPublisherFolder));
+            }
+        }
+    }
+
+    for root in software_roots {
+        for product in &products {
+            let key = registry::join(&registry::join(&root.path, &publisher), product);
+            if registry.open_key(&key).is_ok() {
+                let leftover = Leftover::new(FoundItem::RegistryKey(key), MatchReason::UnderPublisherFolder);
+                results.push(leftover.for_user(root.user.as_deref()).with_key_metadata(registry));
+            }
+        }
+    }
+    results
+}
+
+// Walks each root down to `REGISTRY_MAX_DEPTH`, reporting keys named after the program
+// (without descending into them) and single values whose name or data points into one of
+// `install_paths` (lowercase, without a trailing separator).
+pub fn scan_registry(
+    registry: &dyn RegistryBackend,
+    software_roots: &[SoftwareRoot],
+    search_terms: &SearchTerms,
+    install_paths: &[String],
+    control: &ScanControl,
+) -> Vec<Leftover> {
+    let mut results = Vec::new();
+
+    for root in software_roots {
+        let mut pending = vec![(root.path.clone(), 0)];
+        while let Some((path, depth)) = pending.pop() {
+            if control.is_cancelled() {
+                return results;
+            }
+            for (name, value) in registry.enum_values(&path).unwrap_or_default() {
+                if mentions_any(&name, install_paths) || value_texts(&value).iter().any(|t| mentions_any(t, install_paths)) {
+                    let item = FoundItem::RegistryValue { key: path.clone(), name };
+                    let leftover = Leftover::new(item, MatchReason::ReferencesInstallLocation);
+                    results.push(leftover.for_user(root.user.as_deref()).with_key_metadata(registry));
+                }
+            }
+            if depth == REGISTRY_MAX_DEPTH {
+                continue;
+            }
+            for subkey_name in registry.enum_keys(&path).unwrap_or_default() {
+                let key = registry::join(&path, &subkey_name);
+                // Other roots, such as Wow6432Node below HKLM\SOFTWARE, are walked on their own.
+                if software_roots.iter().any(|other| other.path.eq_ignore_ascii_case(&key)) {
+                    continue;
+                }
+                let matched = match_name(&subkey_name, search_terms);
+                control.visited(matched.is_some());
+                match matched {
+                    // Deleting the key takes its subtree with it, so nothing inside is listed.
+                    Some(reason) => {
+                        let leftover = Leftover::new(FoundItem::RegistryKey(key), reason);
+                        results.push(leftover.for_user(root.user.as_deref()).with_key_metadata(registry));
+                    }
+                    None => pending.push((key, depth + 1)),
+                }
+            }
+        }
+    }
+    results
+}
+
+// The strings a value holds, with `%VAR%` references expanded.
+fn value_texts(value: &RegValue) -> Vec<String> {
+    match value {
+        RegValue::String(s) => vec![s.clone()],
+        RegValue::ExpandString(s) => vec![profiles::expand_env(s)],
+        RegValue::MultiString(list) => list.clone(),
+        _ => vec![],
+    }
+}
+
+// An install folder as `mentions_any` looks for it: lowercase, backslashes, no trailing one.
+fn install_path(dir: &Path) -> String {
+    dir.to_string_lossy().replace('/', "\\").trim_end_matches('\\').to_lowercase()
+}
+
+// Whether `text` contains one of the paths as a whole folder, so `C:\Contoso` is found in
+// `"C:\Contoso\app.exe" /S` but not in `C:\Contoso Tools`.
+fn mentions_any(text: &str, paths: &[String]) -> bool {
+    let text = text.replace('/', "\\").to_lowercase();
+    paths.iter().any(|path| {
+        text.match_indices(path.as_str())
+            .any(|(i, _)| matches!(text[i + path.len()..].chars().next(), None | Some('\\' | '"' | ';' | ',')))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::known_folders::EnvKnownFolders;
+    use crate::registry::MemoryRegistry;
+    use crate::rules::RuleStore;
+    use std::fs;
+
+    fn program(name: &str, install_location: Option<PathBuf>) -> ProgramInfo {
+        ProgramInfo { name: name.into(), version: "1".into(), install_location, ..Default::default() }
+    }
+
+    fn software() -> Vec<SoftwareRoot> {
+        software_roots(&[
+            UserHive { user: "admin".into(), root: "HKEY_CURRENT_USER".into() },
+            UserHive { user: "alice".into(), root: r"HKEY_USERS\S-1-5-21-1-1001".into() },
+        ])
+    }
+
+    #[test]
+    fn registry_scan_matches_subkeys_in_every_root() {
+        let reg = MemoryRegistry::new();
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso Widget").unwrap();
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\ContosoWidget").unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\contoso widget\Settings").unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Unrelated").unwrap();
+        reg.create_key(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso Widget").unwrap();
+
+        let terms = generate_search_terms(&program("Contoso Widget", None));
+        let results = scan_registry(&reg, &software(), &terms, &[], &ScanControl::default());
+
+        let items: Vec<_> = results.iter().map(|r| r.item.clone()).collect();
+        assert_eq!(
+            items,
+            [
+                FoundItem::RegistryKey(r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso Widget".into()),
+                FoundItem::RegistryKey(r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\ContosoWidget".into()),
+                FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\contoso widget".into()),
+                FoundItem::RegistryKey(r"HKEY_USERS\S-1-5-21-1-1001\Software\Contoso Widget".into()),
+            ]
+        );
+        assert!(results.iter().all(|r| r.reason == MatchReason::ExactName));
+        let users: Vec<Option<&str>> = results.iter().map(|r| r.user.as_deref()).collect();
+        assert_eq!(users, [None, None, Some("admin"), Some("alice")]);
+    }
+
+    #[test]
+    fn registry_scan_descends_and_finds_values_pointing_at_the_install() {
+        let reg = MemoryRegistry::new();
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        let shared = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\SharedDLLs";
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Fabrikam\Contoso Widget\Settings").unwrap();
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Fabrikam\ContosoWidget").unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\A\B\C\D\Contoso Widget").unwrap();
+        reg.set_value(run, "Widget", &RegValue::String(r#""C:\Program Files\Contoso\widget.exe" /tray"#.into())).unwrap();
+        reg.set_value(run, "Tools", &RegValue::String(r"C:\Program Files\Contoso Tools\tools.exe".into())).unwrap();
+        reg.set_value(shared, r"c:\program files\contoso\widget.dll", &RegValue::Dword(1)).unwrap();
+        reg.set_value(r"HKEY_CURRENT_USER\Software\Fabrikam", "Paths", &RegValue::MultiString(vec!["C:/Program Files/Contoso".into()])).unwrap();
+
+        let terms = generate_search_terms(&program("Contoso Widget", None));
+        let install = [r"c:\program files\contoso".to_string()];
+        let results = scan_registry(&reg, &software(), &terms, &install, &ScanControl::default());
+
+        let mut items: Vec<String> = results.iter().map(|r| r.item.to_string()).collect();
+        items.sort();
+        assert_eq!(
+            items,
+            [
+                r"[Registry Value] HKEY_CURRENT_USER\Software\Fabrikam\Paths",
+                r"[Registry Value] HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run\Widget",
+                r"[Registry Value] HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\SharedDLLs\c:\program files\contoso\widget.dll",
+                r"[Registry] HKEY_LOCAL_MACHINE\SOFTWARE\Fabrikam\Contoso Widget",
+                r"[Registry] HKEY_LOCAL_MACHINE\SOFTWARE\Wow6432Node\Fabrikam\ContosoWidget",
+            ]
+        );
+        let widget = results.iter().find(|r| matches!(&r.item, FoundItem::RegistryValue { name, .. } if name == "Widget")).unwrap();
+        assert_eq!(widget.reason, MatchReason::ReferencesInstallLocation);
+        assert_eq!(widget.user.as_deref(), Some("admin"));
+        assert!(widget.value_data.as_deref().is_some_and(|d| d.contains("widget.exe")));
+    }
+
+    #[test]
+    fn values_in_a_folder_two_programs_share_are_not_preselected() {
+        let reg = MemoryRegistry::new();
+        let run = r"HKEY_CURRENT_USER\Software\Microsoft\Windows\CurrentVersion\Run";
+        reg.set_value(run, "Suite Tray", &RegValue::String(r"C:\Contoso Suite\tray.exe".into())).unwrap();
+        let suite = PathBuf::from(r"C:\Contoso Suite");
+        let widget = ProgramInfo { registry_key: r"Uninstall\Widget".into(), ..program("Contoso Widget", Some(suite.clone())) };
+        let studio = ProgramInfo { registry_key: r"Uninstall\Studio".into(), ..program("Contoso Studio", Some(suite)) };
+
+        let terms = generate_search_terms(&widget);
+        let mut results = scan_registry(&reg, &software(), &terms, &[r"c:\contoso suite".to_string()], &ScanControl::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].reason, MatchReason::ReferencesInstallLocation);
+        assert!(!results[0].is_preselected());
+
+        mark_shared(&mut results, &widget, &[widget.clone(), studio]);
+        assert_eq!(results[0].shared_with.as_deref(), Some("Contoso Studio"));
+    }
+
+    #[test]
+    fn scores_match_strength() {
+        let terms = generate_search_terms(&program("Contoso", None));
+        assert_eq!(match_name("contoso", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("contoso.ini", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("contoso-updater", &terms), Some(MatchReason::NamePrefix));
+        assert_eq!(match_name("old_contoso_data", &terms), Some(MatchReason::Substring));
+        assert_eq!(match_name("oldcontosodata", &terms), None);
+        assert_eq!(match_name("fabrikam", &terms), None);
+
+        let aggressive = SearchTerms { aggressive: true, ..terms };
+        assert_eq!(match_name("oldcontosodata", &aggressive), Some(MatchReason::Substring));
+        assert_eq!(match_name("contosoupdater", &aggressive), Some(MatchReason::NamePrefix));
+    }
+
+    #[test]
+    fn matches_on_word_boundaries() {
+        let terms = generate_search_terms(&program("Git", None));
+        assert_eq!(match_name("digital", &terms), None);
+        assert_eq!(match_name("Widget", &terms), None);
+        assert_eq!(match_name("git-lfs", &terms), Some(MatchReason::NamePrefix));
+        assert_eq!(match_name("GitHub", &terms), Some(MatchReason::NamePrefix));
+        assert_eq!(match_name("TortoiseGit", &terms), Some(MatchReason::Substring));
+        assert_eq!(match_name("tortoise.git.cache", &terms), Some(MatchReason::Substring));
+
+        // Multi-word names may be spelled with any separators, or none.
+        let terms = generate_search_terms(&program("Contoso Studio", None));
+        assert_eq!(match_name("Contoso_Studio", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("ContosoStudio.settings", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("ContosoStudioHelper", &terms), Some(MatchReason::NamePrefix));
+        assert_eq!(match_name("Old Contoso-Studio Logs", &terms), Some(MatchReason::Substring));
+        assert_eq!(match_name("contosostudiohelper", &terms), None);
+        assert_eq!(name_tokens("HTTPServer_v2.log"), ["http", "server", "v2", "log"]);
+    }
+
+    #[test]
+    fn short_names_only_match_exactly() {
+        let terms = generate_search_terms(&program("R", None));
+        assert_eq!(match_name("r", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("chrome", &terms), None);
+        assert_eq!(match_name("r.exe", &terms), Some(MatchReason::ExactName));
+    }
+
+    #[test]
+    fn matches_inside_install_location_score_highest() {
+        let dir = tempfile::tempdir().unwrap();
+        let install = dir.path().join("Contoso");
+        fs::create_dir_all(install.join("bin")).unwrap();
+        fs::write(install.join("bin").join("contoso-helper.exe"), "").unwrap();
+        fs::write(dir.path().join("my-contoso.txt"), "").unwrap();
+
+        let prog = program("Contoso", Some(install.clone()));
+        let results = scan_filesystem(&[dir.path().to_path_buf()], &generate_search_terms(&prog), std::slice::from_ref(&install), None, DEFAULT_MAX_DEPTH, &ScanControl::default());
+
+        let find = |path: PathBuf| {
+            results
+                .iter()
+                .find(|r| matches!(&r.item, FoundItem::File(p) | FoundItem::Directory(p) if *p == path))
+                .unwrap()
+        };
+        let install_dir = find(install.clone());
+        assert_eq!(install_dir.reason, MatchReason::InsideInstallLocation);
+        assert_eq!(install_dir.metadata.as_ref().and_then(|m| m.files), Some(1));
+        assert_eq!(results.len(), 2);
+        let loose = find(dir.path().join("my-contoso.txt"));
+        assert_eq!(loose.reason, MatchReason::Substring);
+        assert!(!loose.is_preselected());
+    }
+
+    #[test]
+    fn derives_terms_from_uninstall_metadata() {
+        let prog = ProgramInfo {
+            name: "Contoso Studio".into(),
+            display_icon: Some(r"C:\Program Files\Contoso\cstudio.exe,0".into()),
+            registry_key: r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall\{1234-ABCD}".into(),
+            ..Default::default()
+        };
+        let terms = generate_search_terms(&prog);
+        assert_eq!(terms.names, ["contoso studio", "contosostudio", "cstudio", "{1234-abcd}"]);
+        assert_eq!(terms.words, ["contoso", "studio"]);
+    }
+
+    #[test]
+    fn cleans_real_program_names() {
+        // (display name, publisher, words kept, single words matched on their own)
+        type Case = (&'static str, Option<&'static str>, &'static [&'static str], &'static [&'static str]);
+        let cases: [Case; 12] = [
+            ("Mozilla Firefox (x64 en-US)", Some("Mozilla"), &["Mozilla", "Firefox"], &["firefox"]),
+            ("Mozilla Thunderbird 115.6.0 (x86 de)", Some("Mozilla"), &["Mozilla", "Thunderbird"], &["thunderbird"]),
+            ("7-Zip 23.01 (x64)", Some("Igor Pavlov"), &["7-Zip"], &[]),
+            ("Notepad++ (64-bit x64)", Some("Notepad++ Team"), &["Notepad++"], &[]),
+            ("Python 3.12.1 (64-bit)", Some("Python Software Foundation"), &["Python"], &[]),
+            ("Git version 2.43.0", Some("The Git Development Community"), &["Git", "version"], &[]),
+            ("Microsoft Edge Update", Some("Microsoft Corporation"), &["Microsoft", "Edge", "Update"], &["edge"]),
+            ("Microsoft Visual Studio Code", Some("Microsoft Corporation"), &["Microsoft", "Visual", "Studio", "Code"], &["code", "studio", "visual"]),
+            ("Adobe Acrobat Reader DC - Deutsch", Some("Adobe Systems Incorporated"), &["Adobe", "Acrobat", "Reader", "DC", "Deutsch"], &["acrobat", "deutsch", "reader"]),
+            ("VLC media player", Some("VideoLAN"), &["VLC", "media", "player"], &["media", "player", "vlc"]),
+            ("The Witcher 3: Wild Hunt", None, &["The", "Witcher", "3", "Wild", "Hunt"], &["hunt", "wild", "witcher"]),
+            ("Zoom Workplace\u{00AE} 2024 x64", Some("Zoom Video Communications, Inc."), &["Zoom", "Workplace"], &["workplace"]),
+        ];
+        for (name, publisher, words, single) in cases {
+            let prog = ProgramInfo { name: name.into(), publisher: publisher.map(str::to_string), ..Default::default() };
+            assert_eq!(name_words(name), words, "{}", name);
+            assert_eq!(generate_search_terms(&prog).words, single, "{}", name);
+        }
+    }
+
+    #[test]
+    fn whole_names_outweigh_single_words() {
+        let prog = ProgramInfo { name: "Mozilla Firefox (x64 en-US)".into(), publisher: Some("Mozilla".into()), ..Default::default() };
+        let terms = generate_search_terms(&prog);
+        assert_eq!(match_name("mozilla firefox", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("mozillafirefox.ini", &terms), Some(MatchReason::ExactName));
+        assert_eq!(match_name("firefox", &terms), Some(MatchReason::NameWord));
+        assert_eq!(match_name("firefox-cache", &terms), None);
+        assert_eq!(match_name("mozilla", &terms), None);
+
+        // The publisher's folder turns a single word into a strong match.
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Mozilla").join("Firefox").join("Profiles")).unwrap();
+        let results = scan_filesystem(&[dir.path().to_path_buf()], &terms, &[], Some("Mozilla"), DEFAULT_MAX_DEPTH, &ScanControl::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].item, FoundItem::Directory(dir.path().join("Mozilla").join("Firefox")));
+        assert_eq!(results[0].reason, MatchReason::UnderPublisherFolder);
+    }
+
+    #[test]
+    fn normalises_publisher_names() {
+        let with = |publisher: &str| ProgramInfo { publisher: Some(publisher.into()), ..Default::default() };
+        assert_eq!(publisher_folder(&with("Contoso, Inc.")).as_deref(), Some("Contoso"));
+        assert_eq!(publisher_folder(&with("Mozilla Corporation")).as_deref(), Some("Mozilla"));
+        assert_eq!(publisher_folder(&with("Fabrikam Co., Ltd.")).as_deref(), Some("Fabrikam"));
+        assert_eq!(publisher_folder(&with("Inc.")), None);
+    }
+
+    #[test]
+    fn parses_executables_from_commands() {
+        assert_eq!(executable_path(r#""C:\Program Files\App\unins000.exe" /SILENT"#).as_deref(), Some(r"C:\Program Files\App\unins000.exe"));
+        assert_eq!(executable_path(r"C:\App\app.exe,0").as_deref(), Some(r"C:\App\app.exe"));
+        assert_eq!(executable_path(r"C:\App Dir\uninstall.exe /x").as_deref(), Some(r"C:\App Dir\uninstall.exe"));
+        assert_eq!(executable_path(r"C:\İ\app.exe").as_deref(), Some(r"C:\İ\app.exe"));
+        assert_eq!(executable_path(r"C:\İİ\app.EXE,0").as_deref(), Some(r"C:\İİ\app.EXE"));
+
+        let prog = ProgramInfo {
+            uninstall_string: Some(r"MsiExec.exe /X{1234}".into()),
+            display_icon: Some(r#""C:\Windows\System32\msiexec.exe",0"#.into()),
+            install_source: Some(PathBuf::from(r"C:\ProgramData\Package Cache\{1234}\")),
+            registry_key: r"HKEY_LOCAL_MACHINE\Uninstall\{1234}".into(),
+            ..Default::default()
+        };
+        assert_eq!(metadata_dirs(&prog), [PathBuf::from(r"C:\ProgramData\Package Cache\{1234}\")]);
+    }
+
+    #[test]
+    fn finds_publisher_product_folders_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Contoso").join("Studio")).unwrap();
+        let reg = MemoryRegistry::new();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso\Studio").unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso\Other").unwrap();
+
+        let prog = ProgramInfo { name: "Contoso Studio".into(), publisher: Some("Contoso, Inc.".into()), ..Default::default() };
+        let results = scan_publisher_paths(&prog, &[dir.path().to_path_buf()], &software(), &reg);
+
+        let items: Vec<_> = results.iter().map(|r| r.item.clone()).collect();
+        assert_eq!(
+            items,
+            [
+                FoundItem::Directory(dir.path().join("Contoso").join("Studio")),
+                FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso\Studio".into()),
+            ]
+        );
+        assert!(results.iter().all(|r| r.reason == MatchReason::UnderPublisherFolder));
+    }
+
+    #[test]
+    fn matches_in_publisher_folder_are_boosted() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Contoso")).unwrap();
+        fs::write(dir.path().join("Contoso").join("studio-cache.db"), "").unwrap();
+        fs::write(dir.path().join("studio-cache.db"), "").unwrap();
+
+        let terms = SearchTerms { names: vec!["studio".into()], ..Default::default() };
+        let results = scan_filesystem(&[dir.path().to_path_buf()], &terms, &[], Some("Contoso"), DEFAULT_MAX_DEPTH, &ScanControl::default());
+
+        let reason = |path: PathBuf| results.iter().find(|r| r.item == FoundItem::File(path.clone())).unwrap().reason;
+        assert_eq!(reason(dir.path().join("Contoso").join("studio-cache.db")), MatchReason::UnderPublisherFolder);
+        assert_eq!(reason(dir.path().join("studio-cache.db")), MatchReason::NamePrefix);
+    }
+
+    #[test]
+    fn reports_progress_and_stops_when_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("contoso.txt"), "").unwrap();
+        fs::write(dir.path().join("other.txt"), "").unwrap();
+        let terms = SearchTerms { names: vec!["contoso".into()], ..Default::default() };
+        let (listener, mut reports) = tokio::sync::mpsc::unbounded_channel();
+        let control = ScanControl::default().reporting_to(listener);
+
+        let results = scan_filesystem(&[dir.path().to_path_buf()], &terms, &[], None, DEFAULT_MAX_DEPTH, &control);
+        assert_eq!(results.len(), 1);
+        let mut last = ScanProgress::default();
+        while let Ok(ScanEvent::Progress(progress)) = reports.try_recv() {
+            last = progress;
+        }
+        assert_eq!(last.current, dir.path().display().to_string());
+        assert_eq!(last.matches, 1);
+
+        control.cancel();
+        assert!(scan_filesystem(&[dir.path().to_path_buf()], &terms, &[], None, DEFAULT_MAX_DEPTH, &control).is_empty());
+    }
+
+    #[test]
+    fn flags_items_other_programs_share() {
+        let path = |parts: &[&str]| parts.iter().collect::<PathBuf>();
+        let suite = ProgramInfo {
+            registry_key: r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall\Contoso Suite".into(),
+            install_location: Some(path(&["C:", "Program Files", "Contoso", "Suite"])),
+            ..program("Contoso Suite", None)
+        };
+        let editor = ProgramInfo { registry_key: r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso Editor".into(), ..program("Contoso", None) };
+        let mut results = vec![
+            Leftover::new(FoundItem::Directory(path(&["C:", "Program Files", "Contoso"])), MatchReason::ExactName),
+            Leftover::new(FoundItem::Directory(path(&["C:", "ProgramData", "Contoso Suite"])), MatchReason::NamePrefix),
+            Leftover::new(FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into()), MatchReason::ExactName),
+            Leftover::new(FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso Suite".into()), MatchReason::NamePrefix),
+        ];
+
+        mark_shared(&mut results, &editor, &[suite, editor.clone()]);
+
+        let shared: Vec<Option<&str>> = results.iter().map(|l| l.shared_with.as_deref()).collect();
+        assert_eq!(shared, [Some("Contoso Suite"), Some("Contoso Suite"), None, Some("Contoso Suite")]);
+        assert!(!results[0].is_preselected());
+        assert!(results[2].is_preselected());
+    }
+
+    #[test]
+    fn reclaimable_space_counts_nested_items_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("Contoso");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("contoso.log"), "1234").unwrap();
+        fs::write(dir.path().join("contoso.ini"), "12").unwrap();
+
+        let leftovers = [
+            Leftover::new(FoundItem::Directory(folder.clone()), MatchReason::ExactName),
+            Leftover::new(FoundItem::File(folder.join("contoso.log")), MatchReason::ExactName),
+            Leftover::new(FoundItem::File(dir.path().join("contoso.ini")), MatchReason::ExactName),
+            Leftover::new(FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into()), MatchReason::ExactName),
+        ];
+        assert_eq!(reclaimable_bytes(leftovers.iter()), 6);
+    }
+
+    #[tokio::test]
+    async fn streams_each_roots_results_as_it_finishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (program_data, local) = (dir.path().join("ProgramData"), dir.path().join("Local"));
+        fs::create_dir_all(program_data.join("Contoso")).unwrap();
+        fs::create_dir_all(&local).unwrap();
+        fs::write(local.join("contoso.log"), "").unwrap();
+        fs::create_dir_all(dir.path().join("Users")).unwrap();
+        let folders = EnvKnownFolders::from_vars([
+            ("ProgramData", program_data.to_string_lossy()),
+            ("LOCALAPPDATA", local.to_string_lossy()),
+        ]);
+        let options = ScanOptions { profiles_root: Some(dir.path().join("Users")), ..Default::default() };
+        let (listener, mut events) = tokio::sync::mpsc::unbounded_channel();
+
+        let results = scan_for_leftovers(
+            program("Contoso", None),
+            Arc::new(MemoryRegistry::new()),
+            ManifestStore::at(dir.path().join("manifests")),
+            Arc::new(folders),
+            Arc::default(),
+            options,
+            ScanControl::default().reporting_to(listener),
+        )
+        .await
+        .unwrap();
+
+        let mut batches = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let ScanEvent::Found(found) = event {
+                batches.push(found);
+            }
+        }
+        assert_eq!(batches.len(), 2);
+        let mut streamed: Vec<_> = batches.concat().into_iter().map(|l| l.item).collect();
+        let mut expected: Vec<_> = results.into_iter().map(|l| l.item).collect();
+        streamed.sort_by_key(|item| item.to_string());
+        expected.sort_by_key(|item| item.to_string());
+        assert_eq!(streamed, expected);
+    }
+
+    #[tokio::test]
+    async fn matching_rules_replace_name_matching() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_data = dir.path().join("ProgramData");
+        fs::create_dir_all(program_data.join("Contoso")).unwrap();
+        fs::create_dir_all(program_data.join("Fabrikam").join("Shared")).unwrap();
+        fs::create_dir_all(dir.path().join("Users")).unwrap();
+        fs::create_dir_all(dir.path().join("rules")).unwrap();
+        fs::write(
+            dir.path().join("rules").join("contoso.toml"),
+            "display_name = '^Contoso$'\npaths = ['%ProgramData%/Fabrikam/Shared/**']\n",
+        )
+        .unwrap();
+        let rules = RuleStore::at(dir.path().join("rules")).load().unwrap();
+        let folders = EnvKnownFolders::from_vars([("ProgramData", program_data.to_string_lossy())]);
+        let options = ScanOptions { profiles_root: Some(dir.path().join("Users")), ..Default::default() };
+
+        let results = scan_for_leftovers(
+            program("Contoso", None),
+            Arc::new(MemoryRegistry::new()),
+            ManifestStore::at(dir.path().join("manifests")),
+            Arc::new(folders),
+            Arc::new(rules),
+            options,
+            ScanControl::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].item, FoundItem::Directory(program_data.join("Fabrikam").join("Shared")));
+        assert_eq!(results[0].reason, MatchReason::ListedInRule);
+    }
+
+    #[tokio::test]
+    async fn leaves_out_scan_roots_and_protected_items() {
+        let dir = tempfile::tempdir().unwrap();
+        // A root whose own name matches, as with a TEMP folder redirected to `...\Contoso`.
+        let temp = dir.path().join("Contoso");
+        let program_data = dir.path().join("ProgramData");
+        fs::create_dir_all(temp.join("Contoso Cache")).unwrap();
+        fs::create_dir_all(program_data.join("Contoso")).unwrap();
+        fs::create_dir_all(dir.path().join("Users")).unwrap();
+        let folders = EnvKnownFolders::from_vars([
+            ("TEMP", temp.to_string_lossy()),
+            ("ProgramData", program_data.to_string_lossy()),
+        ]);
+        let reg = MemoryRegistry::new();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso").unwrap();
+        reg.create_key(r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso").unwrap();
+        let options = ScanOptions {
+            profiles_root: Some(dir.path().join("Users")),
+            protected: vec![
+                program_data.join("Contoso").to_string_lossy().into_owned(),
+                r"HKEY_LOCAL_MACHINE\SOFTWARE\Contoso".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let results = scan_for_leftovers(
+            program("Contoso", None),
+            Arc::new(reg),
+            ManifestStore::at(dir.path().join("manifests")),
+            Arc::new(folders),
+            Arc::default(),
+            options,
+            ScanControl::default(),
+        )
+        .await
+        .unwrap();
+
+        let mut items: Vec<_> = results.into_iter().map(|l| l.item).collect();
+        items.sort_by_key(|item| item.to_string());
+        assert_eq!(
+            items,
+            [
+                FoundItem::Directory(temp.join("Contoso Cache")),
+                FoundItem::RegistryKey(r"HKEY_CURRENT_USER\Software\Contoso".into()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_install_folders_that_are_shared_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let program_data = dir.path().join("ProgramData");
+        fs::create_dir_all(program_data.join("Fabrikam")).unwrap();
+        fs::write(program_data.join("Fabrikam").join("contoso-export.csv"), "x").unwrap();
+        fs::create_dir_all(dir.path().join("Users")).unwrap();
+        // An Inno Setup uninstaller dropped straight into ProgramData.
+        let uninstaller = format!("{},0", program_data.join("unins000.exe").display());
+        let prog = ProgramInfo { display_icon: Some(uninstaller.clone()), uninstall_string: Some(uninstaller), ..program("Contoso", None) };
+        assert!(!generate_search_terms(&prog).names.contains(&"unins000".to_string()));
+
+        let results = scan_for_leftovers(
+            prog,
+            Arc::new(MemoryRegistry::new()),
+            ManifestStore::at(dir.path().join("manifests")),
+            Arc::new(EnvKnownFolders::from_vars([("ProgramData", program_data.to_string_lossy())])),
+            Arc::default(),
+            ScanOptions { profiles_root: Some(dir.path().join("Users")), ..Default::default() },
+            ScanControl::default(),
+        )
+        .await
+        .unwrap();
+
+        // Another vendor's file that only mentions the name is not "inside the install location".
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].item, FoundItem::File(program_data.join("Fabrikam").join("contoso-export.csv")));
+        assert_eq!(results[0].reason, MatchReason::NamePrefix);
+    }
+
+    #[tokio::test]
+    async fn searches_extra_roots_and_skips_excluded_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let games = dir.path().join("Games");
+        fs::create_dir_all(games.join("Contoso").join("Cache")).unwrap();
+        fs::create_dir_all(games.join("Contoso").join("Saves")).unwrap();
+        fs::create_dir_all(dir.path().join("Users")).unwrap();
+        let reg: Arc<dyn RegistryBackend> = Arc::new(MemoryRegistry::new());
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso").unwrap();
+        let options = ScanOptions {
+            profiles_root: Some(dir.path().join("Users")),
+            extra_roots: vec![games.clone(), dir.path().join("Missing")],
+            exclude_paths: vec!["**/Contoso".into()],
+            exclude_keys: vec![r"^HKEY_CURRENT_USER\\".into()],
+            ..Default::default()
+        };
+        let scan = |options| {
+            scan_for_leftovers(
+                program("Contoso", None),
+                reg.clone(),
+                ManifestStore::at(dir.path().join("manifests")),
+                Arc::new(EnvKnownFolders::from_vars::<&str, &str>([])),
+                Arc::default(),
+                options,
+                ScanControl::default(),
+            )
+        };
+
+        let all = scan(ScanOptions { exclude_paths: Vec::new(), exclude_keys: Vec::new(), ..options.clone() }).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(scan(options).await.unwrap().is_empty());
+        let bad = ScanOptions { exclude_keys: vec!["[".into()], ..Default::default() };
+        assert!(scan(bad).await.is_err());
+    }
+
+    #[test]
+    fn walk_is_depth_limited_and_skips_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("a").join("b").join("c");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("contoso.log"), "").unwrap();
+        fs::write(dir.path().join("a").join("contoso.ini"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a").join("contoso-link")).unwrap();
+
+        let terms = SearchTerms { names: vec!["contoso".into()], ..Default::default() };
+        let results = scan_filesystem(&[dir.path().to_path_buf()], &terms, &[], None, 3, &ScanControl::default());
+
+        let items: Vec<_> = results.into_iter().map(|r| r.item).collect();
+        assert_eq!(items, [FoundItem::File(dir.path().join("a").join("contoso.ini"))]);
+    }
+}
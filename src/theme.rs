@@ -1,4 +1,4 @@
-use iced::widget::{button, checkbox, container, scrollable, text};
+use iced::widget::{button, checkbox, container, scrollable, text, text_editor, text_input};
 use iced::{application, border, color, Color, Theme};
 
 // --- Theme Definition ---
@@ -12,9 +12,13 @@ const ACCENT_BLUE: Color = color!(0x00, 0x78, 0xD4);
 const ACCENT_BLUE_HOVER: Color = color!(0x00, 0x5A, 0x9E);
 const TEXT_PRIMARY: Color = color!(0x00, 0x00, 0x00);
 const BORDER_LIGHT: Color = color!(0xE0, 0xE0, 0xE0);
+const TEXT_DISABLED: Color = color!(0xA0, 0xA0, 0xA0);
 const CONTROL_FILL_HOVER: Color = Color::from_rgba(0.0, 0.0, 0.0, 0.05);
 const ERROR_BACKGROUND: Color = color!(0xFDE7E9);
 const ERROR_FOREGROUND: Color = color!(0xA4262C);
+const CONFIDENCE_HIGH: Color = color!(0x10, 0x7C, 0x10);
+const CONFIDENCE_MEDIUM: Color = color!(0x9D, 0x5D, 0x00);
+const CONFIDENCE_LOW: Color = color!(0x70, 0x70, 0x70);
 
 // --- Implementations ---
 
@@ -34,6 +38,19 @@ pub enum Text {
     #[default]
     Default,
     Error,
+    ConfidenceHigh,
+    ConfidenceMedium,
+    ConfidenceLow,
+}
+
+impl Text {
+    pub fn confidence(score: u8) -> Self {
+        match score {
+            70.. => Text::ConfidenceHigh,
+            50..=69 => Text::ConfidenceMedium,
+            _ => Text::ConfidenceLow,
+        }
+    }
 }
 
 impl text::StyleSheet for Fluent {
@@ -45,6 +62,15 @@ impl text::StyleSheet for Fluent {
             Text::Error => text::Appearance {
                 color: Some(ERROR_FOREGROUND),
             },
+            Text::ConfidenceHigh => text::Appearance {
+                color: Some(CONFIDENCE_HIGH),
+            },
+            Text::ConfidenceMedium => text::Appearance {
+                color: Some(CONFIDENCE_MEDIUM),
+            },
+            Text::ConfidenceLow => text::Appearance {
+                color: Some(CONFIDENCE_LOW),
+            },
         }
     }
 }
@@ -174,4 +200,89 @@ impl checkbox::StyleSheet for Fluent {
         active.background = (if is_checked { ACCENT_BLUE_HOVER } else { CONTROL_FILL_HOVER }).into();
         active
     }
-}
\ No newline at end of file
+}
+// Text fields share their look with the secondary button; focus turns the border blue.
+fn field_border(color: Color) -> border::Border {
+    border::Border {
+        color,
+        width: 1.0,
+        radius: 4.0.into(),
+    }
+}
+
+impl text_input::StyleSheet for Fluent {
+    type Style = Theme;
+
+    fn active(&self, _style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance {
+            background: Color::WHITE.into(),
+            border: field_border(BORDER_LIGHT),
+            icon_color: TEXT_PRIMARY,
+        }
+    }
+
+    fn focused(&self, style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance {
+            border: field_border(ACCENT_BLUE),
+            ..self.active(style)
+        }
+    }
+
+    fn placeholder_color(&self, _style: &Self::Style) -> Color {
+        TEXT_DISABLED
+    }
+
+    fn value_color(&self, _style: &Self::Style) -> Color {
+        TEXT_PRIMARY
+    }
+
+    fn disabled_color(&self, _style: &Self::Style) -> Color {
+        TEXT_DISABLED
+    }
+
+    fn selection_color(&self, _style: &Self::Style) -> Color {
+        color!(0xCC, 0xE4, 0xF7)
+    }
+
+    fn disabled(&self, style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance {
+            background: CONTROL_FILL_HOVER.into(),
+            ..self.active(style)
+        }
+    }
+}
+
+impl text_editor::StyleSheet for Fluent {
+    type Style = Theme;
+
+    fn active(&self, style: &Self::Style) -> text_editor::Appearance {
+        let input = text_input::StyleSheet::active(self, style);
+        text_editor::Appearance { background: input.background, border: input.border }
+    }
+
+    fn focused(&self, style: &Self::Style) -> text_editor::Appearance {
+        let input = text_input::StyleSheet::focused(self, style);
+        text_editor::Appearance { background: input.background, border: input.border }
+    }
+
+    fn placeholder_color(&self, style: &Self::Style) -> Color {
+        text_input::StyleSheet::placeholder_color(self, style)
+    }
+
+    fn value_color(&self, style: &Self::Style) -> Color {
+        text_input::StyleSheet::value_color(self, style)
+    }
+
+    fn disabled_color(&self, style: &Self::Style) -> Color {
+        text_input::StyleSheet::disabled_color(self, style)
+    }
+
+    fn selection_color(&self, style: &Self::Style) -> Color {
+        text_input::StyleSheet::selection_color(self, style)
+    }
+
+    fn disabled(&self, style: &Self::Style) -> text_editor::Appearance {
+        let input = text_input::StyleSheet::disabled(self, style);
+        text_editor::Appearance { background: input.background, border: input.border }
+    }
+}
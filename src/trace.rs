@@ -0,0 +1,249 @@
+use crate::registry::{self, RegistryBackend};
+use crate::FoundItem;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Where installers write registry data; whole hives would take minutes to walk.
+pub const TRACED_REGISTRY_ROOTS: [&str; 3] = [
+    r"HKEY_LOCAL_MACHINE\SOFTWARE",
+    r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Services",
+    r"HKEY_CURRENT_USER\Software",
+];
+
+// --- Snapshots ---
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken: String,
+    pub directories: BTreeSet<PathBuf>,
+    pub files: BTreeSet<PathBuf>,
+    pub registry_keys: BTreeSet<String>,
+}
+
+impl Snapshot {
+    pub fn take(roots: &[PathBuf], registry_roots: &[&str], registry: &dyn RegistryBackend) -> Self {
+        let mut snapshot = Snapshot { taken: chrono::Local::now().to_rfc3339(), ..Default::default() };
+        for root in roots {
+            // Skip the root itself so a root that shows up later isn't reported as the program's.
+            for entry in walkdir::WalkDir::new(root).min_depth(1).into_iter().filter_map(|e| e.ok()) {
+                if entry.file_type().is_dir() {
+                    snapshot.directories.insert(entry.into_path());
+                } else {
+                    snapshot.files.insert(entry.into_path());
+                }
+            }
+        }
+        for root in registry_roots {
+            collect_keys(registry, root, &mut snapshot.registry_keys);
+        }
+        snapshot
+    }
+}
+
+fn collect_keys(registry: &dyn RegistryBackend, path: &str, keys: &mut BTreeSet<String>) {
+    for child in registry.enum_keys(path).unwrap_or_default() {
+        let child_path = registry::join(path, &child);
+        collect_keys(registry, &child_path, keys);
+        keys.insert(child_path);
+    }
+}
+
+// --- Install Manifests ---
+
+// Everything an install created, reduced to the topmost new folders and keys: a new
+// folder's contents are not listed separately, since deleting the folder removes them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallManifest {
+    pub program: String,
+    // The program's Uninstall key, which identifies it across scans.
+    pub registry_key: String,
+    pub created: String,
+    pub directories: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+    pub registry_keys: Vec<String>,
+}
+
+impl InstallManifest {
+    pub fn diff(before: &Snapshot, after: &Snapshot) -> Self {
+        let mut directories: Vec<PathBuf> = Vec::new();
+        for dir in after.directories.difference(&before.directories) {
+            // The set is sorted, so a new folder's descendants directly follow it.
+            if !directories.last().is_some_and(|parent| dir.starts_with(parent)) {
+                directories.push(dir.clone());
+            }
+        }
+        let files = after
+            .files
+            .difference(&before.files)
+            .filter(|file| !directories.iter().any(|dir| file.starts_with(dir)))
+            .cloned()
+            .collect();
+
+        let mut registry_keys: Vec<String> = Vec::new();
+        for key in after.registry_keys.difference(&before.registry_keys) {
+            if !registry_keys.iter().any(|parent| is_under_key(key, parent)) {
+                registry_keys.push(key.clone());
+            }
+        }
+
+        InstallManifest { created: chrono::Local::now().to_rfc3339(), directories, files, registry_keys, ..Default::default() }
+    }
+
+    pub fn items(&self) -> Vec<FoundItem> {
+        let directories = self.directories.iter().cloned().map(FoundItem::Directory);
+        let files = self.files.iter().cloned().map(FoundItem::File);
+        let keys = self.registry_keys.iter().cloned().map(FoundItem::RegistryKey);
+        directories.chain(files).chain(keys).collect()
+    }
+}
+
+pub fn is_under_key(key: &str, parent: &str) -> bool {
+    key.len() > parent.len()
+        && key.as_bytes()[parent.len()] == b'\\'
+        && key[..parent.len()].eq_ignore_ascii_case(parent)
+}
+
+// --- Persistence ---
+
+// Manifests are stored one file per program, next to the pending "before" snapshot.
+#[derive(Debug, Clone)]
+pub struct ManifestStore {
+    dir: PathBuf,
+}
+
+impl ManifestStore {
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        ManifestStore { dir: dir.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|d| ManifestStore::at(d.join("KuriUninstaller").join("manifests")))
+    }
+
+    fn manifest_path(&self, registry_key: &str) -> PathBuf {
+        let file_name: String = registry_key
+            .chars()
+            .map(|c| if c.is_alphanumeric() || "{}-_. ".contains(c) { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", file_name))
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        self.dir.join("pending-snapshot.json")
+    }
+
+    pub fn save(&self, manifest: &InstallManifest) -> Result<(), String> {
+        write_json(&self.manifest_path(&manifest.registry_key), manifest)
+    }
+
+    pub fn load(&self, registry_key: &str) -> Result<Option<InstallManifest>, String> {
+        if registry_key.is_empty() {
+            return Ok(None);
+        }
+        read_json(&self.manifest_path(registry_key))
+    }
+
+    // The "before" snapshot lives on disk so a trace survives installers that need a restart.
+    pub fn save_snapshot(&self, snapshot: &Snapshot) -> Result<(), String> {
+        write_json(&self.snapshot_path(), snapshot)
+    }
+
+    pub fn load_snapshot(&self) -> Result<Option<Snapshot>, String> {
+        read_json(&self.snapshot_path())
+    }
+
+    pub fn discard_snapshot(&self) -> Result<(), String> {
+        match fs::remove_file(self.snapshot_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn has_pending_snapshot(&self) -> bool {
+        self.snapshot_path().exists()
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map(Some).map_err(|e| format!("Corrupt file {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    #[test]
+    fn diff_reports_only_topmost_new_items() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        fs::create_dir_all(root.join("Existing")).unwrap();
+        fs::write(root.join("Existing").join("old.txt"), "").unwrap();
+        let reg = MemoryRegistry::new();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Existing").unwrap();
+
+        let before = Snapshot::take(std::slice::from_ref(&root), &TRACED_REGISTRY_ROOTS, &reg);
+
+        fs::create_dir_all(root.join("Contoso").join("Cache")).unwrap();
+        fs::write(root.join("Contoso").join("Cache").join("a.bin"), "").unwrap();
+        fs::create_dir_all(root.join("Contoso Tools")).unwrap();
+        fs::write(root.join("Existing").join("contoso.ini"), "").unwrap();
+        reg.set_value(r"HKEY_CURRENT_USER\Software\Contoso\Settings", "A", &RegValue::Dword(1)).unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Contoso Tools").unwrap();
+        reg.create_key(r"HKEY_CURRENT_USER\Software\Existing\Contoso").unwrap();
+
+        let after = Snapshot::take(std::slice::from_ref(&root), &TRACED_REGISTRY_ROOTS, &reg);
+        let manifest = InstallManifest::diff(&before, &after);
+
+        assert_eq!(manifest.directories, [root.join("Contoso"), root.join("Contoso Tools")]);
+        assert_eq!(manifest.files, [root.join("Existing").join("contoso.ini")]);
+        assert_eq!(
+            manifest.registry_keys,
+            [
+                r"HKEY_CURRENT_USER\Software\Contoso",
+                r"HKEY_CURRENT_USER\Software\Contoso Tools",
+                r"HKEY_CURRENT_USER\Software\Existing\Contoso",
+            ]
+        );
+    }
+
+    #[test]
+    fn stores_manifests_and_pending_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ManifestStore::at(dir.path().join("manifests"));
+        let key = r"HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall\{ABC}";
+        assert_eq!(store.load(key).unwrap(), None);
+
+        let manifest = InstallManifest {
+            program: "Contoso".into(),
+            registry_key: key.into(),
+            files: vec![PathBuf::from("a.txt")],
+            ..Default::default()
+        };
+        store.save(&manifest).unwrap();
+        assert_eq!(store.load(key).unwrap(), Some(manifest));
+        assert_eq!(store.load("").unwrap(), None);
+
+        let snapshot = Snapshot { taken: "now".into(), ..Default::default() };
+        store.save_snapshot(&snapshot).unwrap();
+        assert!(store.has_pending_snapshot());
+        assert_eq!(store.load_snapshot().unwrap(), Some(snapshot));
+        store.discard_snapshot().unwrap();
+        assert!(!store.has_pending_snapshot());
+        assert_eq!(store.load_snapshot().unwrap(), None);
+    }
+}
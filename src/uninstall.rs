@@ -0,0 +1,388 @@
+use crate::ProgramInfo;
+use serde::Serialize;
+use std::io;
+use std::process::Command;
+
+// Exit codes Windows Installer and most setup engines use for "done".
+const SUCCESS_EXIT_CODES: [i32; 3] = [0, 1641, 3010];
+
+// --- Uninstall Commands ---
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UninstallCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UninstallOutcome {
+    pub command: String,
+    // `None` when the uninstaller was killed by a signal rather than exiting.
+    pub exit_code: Option<i32>,
+}
+
+impl UninstallOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code.is_some_and(|code| SUCCESS_EXIT_CODES.contains(&code))
+    }
+
+    pub fn describe(&self) -> String {
+        let meaning = match self.exit_code {
+            Some(0) => "success",
+            Some(1602) => "cancelled by the user",
+            Some(1605) => "product is not installed",
+            Some(1641) | Some(3010) => "success, restart required",
+            Some(_) => "failed",
+            None => "terminated",
+        };
+        match self.exit_code {
+            Some(code) => format!("Uninstaller exited with code {} ({})", code, meaning),
+            None => format!("Uninstaller {}", meaning),
+        }
+    }
+}
+
+impl std::fmt::Display for UninstallCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\"", self.program)?;
+        for arg in &self.args {
+            if arg.contains(' ') {
+                write!(f, " \"{}\"", arg)?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Picks the registered uninstaller. `quiet` prefers `QuietUninstallString` and asks
+// Windows Installer for an unattended removal.
+pub fn uninstall_command(program: &ProgramInfo, quiet: bool) -> Result<UninstallCommand, String> {
+    let registered = if quiet {
+        program.quiet_uninstall_string.as_ref().or(program.uninstall_string.as_ref())
+    } else {
+        program.uninstall_string.as_ref().or(program.quiet_uninstall_string.as_ref())
+    };
+    let registered = registered.ok_or_else(|| format!("{} has no registered uninstaller", program.name))?;
+
+    let command = parse_command_line(registered)?;
+    if !is_msiexec(&command.program) {
+        return Ok(command);
+    }
+
+    // `MsiExec.exe /I{GUID}` opens the repair/modify dialog; always ask for removal instead.
+    let product_code = msi_product_code(&command.args)
+        .ok_or_else(|| format!("No product code in MsiExec command: {}", registered))?;
+    let mut args = vec!["/X".to_string(), product_code];
+    if quiet {
+        args.extend(["/qn".to_string(), "/norestart".to_string()]);
+    }
+    Ok(UninstallCommand { program: command.program, args })
+}
+
+// Splits a registry command line into the executable and its arguments. Vendors often
+// leave paths with spaces unquoted, so an unquoted program runs up to `.exe`.
+pub fn parse_command_line(command: &str) -> Result<UninstallCommand, String> {
+    let command = command.trim();
+    let (program, rest) = if let Some(quoted) = command.strip_prefix('"') {
+        let end = quoted.find('"').ok_or_else(|| format!("Unterminated quote in command: {}", command))?;
+        (&quoted[..end], &quoted[end + 1..])
+    } else {
+        // ASCII lowercasing keeps byte offsets, so `end` also indexes `command`.
+        let end = command
+            .to_ascii_lowercase()
+            .find(".exe")
+            .map(|i| i + 4)
+            .or_else(|| command.find(' '))
+            .unwrap_or(command.len());
+        (&command[..end], &command[end..])
+    };
+    if program.trim().is_empty() {
+        return Err("Uninstall command is empty".to_string());
+    }
+    Ok(UninstallCommand { program: program.trim().to_string(), args: split_arguments(rest) })
+}
+
+// Whitespace-separated arguments, where double quotes group (and are removed).
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+fn is_msiexec(program: &str) -> bool {
+    let file_name = program.rsplit(['\\', '/']).next().unwrap_or(program).to_lowercase();
+    file_name == "msiexec" || file_name == "msiexec.exe"
+}
+
+// Finds the `{GUID}` after `/X` or `/I`, written either as `/X{GUID}` or `/X {GUID}`.
+fn msi_product_code(args: &[String]) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        let upper = arg.to_uppercase();
+        let rest = upper.strip_prefix("/X").or_else(|| upper.strip_prefix("/I"))?;
+        let code = if rest.is_empty() { args.get(i + 1)?.to_uppercase() } else { rest.to_string() };
+        (code.starts_with('{') && code.ends_with('}')).then_some(code)
+    })
+}
+
+// --- Process Supervision ---
+
+// Runs the uninstaller and blocks until it and every process it started have exited.
+// Many uninstallers copy themselves to %TEMP% and relaunch, so the first process
+// exiting says little about whether the uninstall is finished.
+pub fn run(command: &UninstallCommand) -> Result<UninstallOutcome, String> {
+    let mut process = Command::new(&command.program);
+    process.args(&command.args);
+    let exit_code = wait_for_process_tree(process).map_err(|e| format!("Failed to run {}: {}", command.program, e))?;
+    Ok(UninstallOutcome { command: command.to_string(), exit_code })
+}
+
+pub async fn run_uninstaller(command: UninstallCommand) -> Result<UninstallOutcome, String> {
+    tokio::task::spawn_blocking(move || run(&command)).await.map_err(|e| e.to_string())?
+}
+
+// The uninstaller gets its own process group; with this process as a child subreaper,
+// orphaned grandchildren are reparented here and can be waited for like children.
+#[cfg(unix)]
+fn wait_for_process_tree(command: Command) -> io::Result<Option<i32>> {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1);
+    }
+    let result = wait_for_process_group(command);
+    // Only while the uninstaller runs: anything else orphaned later goes to init as usual.
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 0);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn wait_for_process_group(mut command: Command) -> io::Result<Option<i32>> {
+    use std::os::unix::process::{CommandExt, ExitStatusExt};
+
+    command.process_group(0);
+    let child = command.spawn()?;
+    let group = child.id() as libc::pid_t;
+
+    let mut exit_code = None;
+    loop {
+        let mut status = 0;
+        let pid = unsafe { libc::waitpid(-group, &mut status, 0) };
+        if pid == -1 {
+            let error = io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EINTR) => continue,
+                Some(libc::ECHILD) => break,
+                _ => return Err(error),
+            }
+        }
+        if pid == group {
+            exit_code = std::process::ExitStatus::from_raw(status).code();
+        }
+    }
+    Ok(exit_code)
+}
+
+// Windows has no process groups to wait on; a job object collects every descendant instead.
+#[cfg(windows)]
+fn wait_for_process_tree(mut command: Command) -> io::Result<Option<i32>> {
+    use std::os::windows::io::AsRawHandle;
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicAccountingInformation, QueryInformationJobObject,
+        JOBOBJECT_BASIC_ACCOUNTING_INFORMATION,
+    };
+    use windows_sys::Win32::System::Threading::CREATE_SUSPENDED;
+
+    let job = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+    if job == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Started suspended, so it cannot launch its relocated copy before it is in the job.
+    command.creation_flags(CREATE_SUSPENDED);
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            unsafe { CloseHandle(job) };
+            return Err(e);
+        }
+    };
+    let assigned = unsafe { AssignProcessToJobObject(job, child.as_raw_handle() as _) } != 0;
+    if let Err(e) = resume_main_thread(child.id()) {
+        let _ = child.kill();
+        unsafe { CloseHandle(job) };
+        return Err(e);
+    }
+    let status = child.wait();
+
+    // If the child could not join the job, fall back to waiting for the child alone.
+    if assigned {
+        loop {
+            let mut info: JOBOBJECT_BASIC_ACCOUNTING_INFORMATION = unsafe { std::mem::zeroed() };
+            let queried = unsafe {
+                QueryInformationJobObject(
+                    job,
+                    JobObjectBasicAccountingInformation,
+                    &mut info as *mut _ as *mut _,
+                    std::mem::size_of::<JOBOBJECT_BASIC_ACCOUNTING_INFORMATION>() as u32,
+                    std::ptr::null_mut(),
+                )
+            } != 0;
+            if !queried || info.ActiveProcesses == 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+    }
+    unsafe { CloseHandle(job) };
+    Ok(status?.code())
+}
+
+// `Command` keeps the thread handle of the process it started to itself, so the suspended
+// main thread is looked up by process ID; a new process has no other threads yet.
+#[cfg(windows)]
+fn resume_main_thread(pid: u32) -> io::Result<()> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+    };
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    let mut resumed = false;
+    let mut more = unsafe { Thread32First(snapshot, &mut entry) } != 0;
+    while more {
+        if entry.th32OwnerProcessID == pid {
+            let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, entry.th32ThreadID) };
+            if thread != 0 {
+                resumed |= unsafe { ResumeThread(thread) } != u32::MAX;
+                unsafe { CloseHandle(thread) };
+            }
+        }
+        more = unsafe { Thread32Next(snapshot, &mut entry) } != 0;
+    }
+    unsafe { CloseHandle(snapshot) };
+    if resumed {
+        Ok(())
+    } else {
+        Err(io::Error::other("Could not start the uninstaller's main thread"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(uninstall: Option<&str>, quiet: Option<&str>) -> ProgramInfo {
+        ProgramInfo {
+            name: "Contoso".into(),
+            uninstall_string: uninstall.map(String::from),
+            quiet_uninstall_string: quiet.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    fn command(program: &str, args: &[&str]) -> UninstallCommand {
+        UninstallCommand { program: program.into(), args: args.iter().map(|a| a.to_string()).collect() }
+    }
+
+    #[test]
+    fn parses_registered_command_lines() {
+        assert_eq!(
+            parse_command_line(r#""C:\Program Files\Contoso\unins000.exe" /SILENT "/LOG=C:\a b.txt""#).unwrap(),
+            command(r"C:\Program Files\Contoso\unins000.exe", &["/SILENT", r"/LOG=C:\a b.txt"])
+        );
+        assert_eq!(
+            parse_command_line(r"C:\Program Files\Contoso\uninstall.exe --mode=remove").unwrap(),
+            command(r"C:\Program Files\Contoso\uninstall.exe", &["--mode=remove"])
+        );
+        assert_eq!(parse_command_line("/usr/bin/true").unwrap(), command("/usr/bin/true", &[]));
+        assert!(parse_command_line(r#""C:\Contoso\unins000.exe"#).is_err());
+        assert!(parse_command_line("  ").is_err());
+        // Lowercasing `İ` adds a byte, which must not shift where the program ends.
+        assert_eq!(parse_command_line(r"C:\İİ\uninst.EXE /S").unwrap(), command(r"C:\İİ\uninst.EXE", &["/S"]));
+        assert_eq!(parse_command_line(r"C:\İ\app.exe").unwrap(), command(r"C:\İ\app.exe", &[]));
+    }
+
+    #[test]
+    fn rewrites_msiexec_commands_to_removal() {
+        let guid = "{12345678-ABCD-EF00-0000-000000000000}";
+        let interactive = uninstall_command(&program(Some(&format!("MsiExec.exe /I{}", guid)), None), false).unwrap();
+        assert_eq!(interactive, command("MsiExec.exe", &["/X", guid]));
+
+        let quiet = uninstall_command(&program(Some(&format!("msiexec /x {}", guid.to_lowercase())), None), true).unwrap();
+        assert_eq!(quiet, command("msiexec", &["/X", guid, "/qn", "/norestart"]));
+
+        assert!(uninstall_command(&program(Some("MsiExec.exe /qb"), None), false).is_err());
+    }
+
+    #[test]
+    fn prefers_the_quiet_command_only_when_asked() {
+        let prog = program(Some(r"C:\a\uninst.exe"), Some(r"C:\a\uninst.exe /S"));
+        assert_eq!(uninstall_command(&prog, false).unwrap(), command(r"C:\a\uninst.exe", &[]));
+        assert_eq!(uninstall_command(&prog, true).unwrap(), command(r"C:\a\uninst.exe", &["/S"]));
+        assert_eq!(uninstall_command(&program(None, Some("quiet.exe")), false).unwrap(), command("quiet.exe", &[]));
+        assert!(uninstall_command(&program(None, None), false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn waits_for_the_uninstaller_and_its_children() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A stub uninstaller that exits at once but leaves a child behind, the way
+        // self-relocating uninstallers do.
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("finished");
+        let stub = dir.path().join("uninstall.sh");
+        std::fs::write(&stub, format!("#!/bin/sh\n(sleep 1; touch '{}') &\nexit 7\n", marker.display())).unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let outcome = run(&command(&stub.to_string_lossy(), &[])).unwrap();
+        assert_eq!(outcome.exit_code, Some(7));
+        assert!(!outcome.succeeded());
+        assert!(marker.exists());
+
+        assert!(run(&command(&dir.path().join("missing").to_string_lossy(), &[])).is_err());
+
+        // Orphans of anything else this process starts are no longer reparented here.
+        #[cfg(target_os = "linux")]
+        {
+            let mut subreaper: libc::c_int = -1;
+            unsafe { libc::prctl(libc::PR_GET_CHILD_SUBREAPER, &mut subreaper as *mut libc::c_int) };
+            assert_eq!(subreaper, 0);
+        }
+    }
+}